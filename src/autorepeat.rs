@@ -0,0 +1,186 @@
+//! Key autorepeat on playback
+//!
+//! A key held down on a real keyboard repeats: after a delay the kernel sends
+//! value-2 events for it, one per period, and consoles and many applications
+//! type a character for each. Readable macros only keep when keys go down
+//! and up, so on request the repeats are generated again on playback the way
+//! the kernel does it. Raw macros contain the recorded repeats and can play
+//! those as-is.
+//!
+//! The repeats are generated here rather than by the kernel: evdev's uinput
+//! builder can't enable EV_REP on the virtual devices, and generating them
+//! ourselves keeps them on the macro's timeline, so pausing or aborting
+//! playback stops them too.
+
+use crate::keymap;
+use crate::recorder::RecordedEvent;
+use evdev::{EventType, InputEvent};
+use std::time::Duration;
+
+/// The kernel's default EV_REP delay before a held key starts repeating
+pub const DEFAULT_DELAY: Duration = Duration::from_millis(250);
+/// The kernel's default EV_REP period between repeats
+pub const DEFAULT_PERIOD: Duration = Duration::from_millis(33);
+
+/// Where key repeats on playback come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyRepeat {
+    /// Play the repeats the macro contains, if any
+    Recorded,
+    /// Replace the macro's repeats with ones generated like the kernel's,
+    /// with the same meaning as EV_REP's REP_DELAY and REP_PERIOD
+    Synthesized { delay: Duration, period: Duration },
+    /// Leave all repeats out
+    Off,
+}
+
+impl KeyRepeat {
+    /// Generated repeats with the kernel's default timing
+    pub fn synthesized() -> Self {
+        KeyRepeat::Synthesized {
+            delay: DEFAULT_DELAY,
+            period: DEFAULT_PERIOD,
+        }
+    }
+
+    /// Produce the events to play, with the repeats changed to match
+    ///
+    /// Like the kernel, only the key pressed last repeats, and releasing any
+    /// key stops it. Buttons never repeat.
+    pub fn apply(&self, events: &[RecordedEvent]) -> Vec<RecordedEvent> {
+        let is_repeat = |recorded: &RecordedEvent| {
+            recorded.event.event_type() == EventType::KEY && recorded.event.value() == 2
+        };
+
+        let (delay_us, period_us) = match *self {
+            KeyRepeat::Recorded => return events.to_vec(),
+            KeyRepeat::Off => {
+                return events.iter().filter(|e| !is_repeat(e)).cloned().collect();
+            }
+            KeyRepeat::Synthesized { delay, period } => {
+                (delay.as_micros() as u64, (period.as_micros() as u64).max(1))
+            }
+        };
+
+        let mut result = Vec::with_capacity(events.len());
+        // The repeating key and when it repeats next
+        let mut repeating: Option<(u16, u64)> = None;
+
+        for recorded in events {
+            while let Some((code, next_us)) = repeating {
+                if next_us >= recorded.timestamp_us {
+                    break;
+                }
+                result.push(RecordedEvent {
+                    timestamp_us: next_us,
+                    event: InputEvent::new(EventType::KEY.0, code, 2),
+                });
+                result.push(RecordedEvent {
+                    timestamp_us: next_us,
+                    event: InputEvent::new(EventType::SYNCHRONIZATION.0, 0, 0),
+                });
+                repeating = Some((code, next_us.saturating_add(period_us)));
+            }
+
+            if is_repeat(recorded) {
+                continue;
+            }
+
+            let event = recorded.event;
+            if event.event_type() == EventType::KEY && !keymap::is_button(event.code()) {
+                match event.value() {
+                    1 => {
+                        let next_us = recorded.timestamp_us.saturating_add(delay_us);
+                        repeating = Some((event.code(), next_us));
+                    }
+                    0 => repeating = None,
+                    _ => {}
+                }
+            }
+
+            result.push(recorded.clone());
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(timestamp_ms: u64, code: u16, value: i32) -> RecordedEvent {
+        RecordedEvent {
+            timestamp_us: timestamp_ms * 1000,
+            event: InputEvent::new(EventType::KEY.0, code, value),
+        }
+    }
+
+    /// The key events as (time in ms, code, value)
+    fn keys(events: &[RecordedEvent]) -> Vec<(u64, u16, i32)> {
+        events
+            .iter()
+            .filter(|e| e.event.event_type() == EventType::KEY)
+            .map(|e| (e.timestamp_us / 1000, e.event.code(), e.event.value()))
+            .collect()
+    }
+
+    #[test]
+    fn test_synthesized_repeats() {
+        let repeat = KeyRepeat::Synthesized {
+            delay: Duration::from_millis(100),
+            period: Duration::from_millis(50),
+        };
+        // BACKSPACE held 220ms, with a recorded repeat that gets replaced
+        let events = vec![key(0, 14, 1), key(130, 14, 2), key(220, 14, 0)];
+
+        assert_eq!(
+            keys(&repeat.apply(&events)),
+            vec![(0, 14, 1), (100, 14, 2), (150, 14, 2), (200, 14, 2), (220, 14, 0)]
+        );
+    }
+
+    #[test]
+    fn test_only_last_key_repeats() {
+        let repeat = KeyRepeat::Synthesized {
+            delay: Duration::from_millis(100),
+            period: Duration::from_millis(100),
+        };
+        let events = vec![
+            key(0, 17, 1),     // W
+            key(50, 30, 1),    // A takes over before W repeats
+            key(200, 17, 0),   // releasing W stops A repeating too
+            key(300, 30, 0),
+            key(300, 272, 1),  // BTN_LEFT never repeats
+            key(800, 272, 0),
+            key(800, 544, 1),  // nor does BTN_DPAD_UP
+            key(1300, 544, 0),
+        ];
+
+        assert_eq!(
+            keys(&repeat.apply(&events)),
+            vec![
+                (0, 17, 1),
+                (50, 30, 1),
+                (150, 30, 2),
+                (200, 17, 0),
+                (300, 30, 0),
+                (300, 272, 1),
+                (800, 272, 0),
+                (800, 544, 1),
+                (1300, 544, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recorded_and_off() {
+        let events = vec![key(0, 14, 1), key(250, 14, 2), key(300, 14, 0)];
+
+        assert_eq!(keys(&KeyRepeat::Recorded.apply(&events)), keys(&events));
+        assert_eq!(
+            keys(&KeyRepeat::Off.apply(&events)),
+            vec![(0, 14, 1), (300, 14, 0)]
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! User configuration file
+//!
+//! Read from `$XDG_CONFIG_HOME/evkey/config` (or `~/.config/evkey/config`).
+//! Simple `key = value` lines, `#` starts a comment:
+//!   start_hotkey = CTRL+ALT+R
+//!   stop_hotkey = CTRL+ALT+S
+//!   pause_hotkey = CTRL+ALT+P
+//!   discard_hotkey = CTRL+ALT+D
+//!
+//!   abort_hotkey = CTRL+ALT+Q
+//!   play_pause_hotkey = CTRL+ALT+SPACE
+//!
+//!   mouse_threshold = 5
+//!   simplify_mouse = 2
+//!   quantize = 1ms
+//!
+//! `record_hotkey` sets both the start and stop hotkey.
+
+use crate::hotkey::Hotkey;
+use crate::monitor::PlayHotkeys;
+use crate::recorder::RecordHotkeys;
+use crate::state::MouseFilter;
+use crate::storage;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Settings loaded from the config file
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// Hotkeys that control recording
+    pub record_hotkeys: RecordHotkeys,
+    /// Hotkeys that control playback
+    pub play_hotkeys: PlayHotkeys,
+    /// How recorded mouse movement is simplified
+    pub mouse_filter: MouseFilter,
+    /// Granularity of timings in recorded macros, if set
+    pub quantize: Option<Duration>,
+}
+
+impl Config {
+    /// Load the config file, falling back to defaults if it doesn't exist
+    pub fn load() -> io::Result<Self> {
+        match config_path() {
+            Some(path) if path.exists() => Self::parse(&fs::read_to_string(path)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Parse config file contents
+    fn parse(contents: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+
+        for (line_num, line) in contents.lines().enumerate() {
+            let line = line.trim();
+
+            // Skip empty lines and comments
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("Line {}: expected 'key = value'", line_num + 1))?;
+
+            let key = key.trim();
+            let hotkey = || {
+                Hotkey::parse(value.trim()).map_err(|e| format!("Line {}: {}", line_num + 1, e))
+            };
+            let pixels = || match value.trim().parse::<f64>() {
+                Ok(pixels) if pixels.is_finite() && pixels >= 0.0 => Ok(pixels),
+                _ => Err(format!("Line {}: expected a number of pixels", line_num + 1)),
+            };
+            let hotkeys = &mut config.record_hotkeys;
+
+            match key {
+                "record_hotkey" => {
+                    hotkeys.start = hotkey()?;
+                    hotkeys.stop = hotkeys.start.clone();
+                }
+                "start_hotkey" => hotkeys.start = hotkey()?,
+                "stop_hotkey" => hotkeys.stop = hotkey()?,
+                "pause_hotkey" => hotkeys.pause = hotkey()?,
+                "discard_hotkey" => hotkeys.discard = hotkey()?,
+                "abort_hotkey" => config.play_hotkeys.abort = hotkey()?,
+                "play_pause_hotkey" => config.play_hotkeys.pause = hotkey()?,
+                "mouse_threshold" => {
+                    config.mouse_filter.threshold = match value.trim().parse::<i32>() {
+                        Ok(pixels) if pixels >= 0 => pixels,
+                        _ => {
+                            return Err(format!(
+                                "Line {}: expected a whole number of pixels",
+                                line_num + 1
+                            ));
+                        }
+                    };
+                }
+                "simplify_mouse" => config.mouse_filter.simplify = Some(pixels()?),
+                "quantize" => {
+                    let step = storage::parse_duration(value.trim())
+                        .map_err(|e| format!("Line {}: {}", line_num + 1, e))?;
+                    config.quantize = Some(step);
+                }
+                other => {
+                    return Err(format!("Line {}: unknown setting '{}'", line_num + 1, other));
+                }
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Location of the config file
+fn config_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("evkey").join("config"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_empty() {
+        let config = Config::parse("# nothing here\n\n").unwrap();
+        assert_eq!(config.record_hotkeys.start, Hotkey::default());
+        assert_eq!(config.record_hotkeys.stop, Hotkey::default());
+    }
+
+    #[test]
+    fn test_parse_record_hotkey() {
+        let config = Config::parse("record_hotkey = CTRL+ALT+R\n").unwrap();
+        assert_eq!(config.record_hotkeys.start.to_string(), "CTRL+ALT+R");
+        assert_eq!(config.record_hotkeys.stop.to_string(), "CTRL+ALT+R");
+    }
+
+    #[test]
+    fn test_parse_separate_hotkeys() {
+        let config = Config::parse(
+            "start_hotkey = F5\nstop_hotkey = F6\npause_hotkey = F7\ndiscard_hotkey = F8\n",
+        )
+        .unwrap();
+        let hotkeys = config.record_hotkeys;
+        assert_eq!(hotkeys.start.to_string(), "F5");
+        assert_eq!(hotkeys.stop.to_string(), "F6");
+        assert_eq!(hotkeys.pause.to_string(), "F7");
+        assert_eq!(hotkeys.discard.to_string(), "F8");
+    }
+
+    #[test]
+    fn test_parse_play_hotkeys() {
+        let config =
+            Config::parse("abort_hotkey = CTRL+ALT+Q\nplay_pause_hotkey = F9\n").unwrap();
+        assert_eq!(config.play_hotkeys.abort.to_string(), "CTRL+ALT+Q");
+        assert_eq!(config.play_hotkeys.pause.to_string(), "F9");
+        assert_eq!(config.record_hotkeys.stop, Hotkey::default());
+    }
+
+    #[test]
+    fn test_parse_mouse_filter() {
+        let config = Config::parse("mouse_threshold = 3\nsimplify_mouse = 1.5\n").unwrap();
+        assert_eq!(config.mouse_filter.threshold, 3);
+        assert_eq!(config.mouse_filter.simplify, Some(1.5));
+        assert_eq!(Config::parse("").unwrap().mouse_filter, MouseFilter::default());
+        assert!(Config::parse("mouse_threshold = -1").is_err());
+        assert!(Config::parse("mouse_threshold = 2.5").is_err());
+    }
+
+    #[test]
+    fn test_parse_quantize() {
+        let config = Config::parse("quantize = 100us\n").unwrap();
+        assert_eq!(config.quantize, Some(Duration::from_micros(100)));
+        assert!(Config::parse("quantize = 1").is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Config::parse("record_hotkey CTRL").is_err());
+        assert!(Config::parse("record_hotkey = NOPE").is_err());
+        assert!(Config::parse("unknown = 1").is_err());
+    }
+}
@@ -0,0 +1,340 @@
+//! Finding and selecting input devices under /dev/input
+
+use evdev::{AbsoluteAxisCode, AttributeSetRef, Device, KeyCode, PropType};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What sort of input device something is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    Keyboard,
+    Mouse,
+    KeyboardMouse,
+    Tablet,
+    Touchscreen,
+    Touchpad,
+    Gamepad,
+    Joystick,
+}
+
+impl DeviceKind {
+    const ALL: [DeviceKind; 8] = [
+        DeviceKind::Keyboard,
+        DeviceKind::Mouse,
+        DeviceKind::KeyboardMouse,
+        DeviceKind::Tablet,
+        DeviceKind::Touchscreen,
+        DeviceKind::Touchpad,
+        DeviceKind::Gamepad,
+        DeviceKind::Joystick,
+    ];
+
+    /// Name used on the console and in macro files
+    pub fn name(self) -> &'static str {
+        match self {
+            DeviceKind::Keyboard => "keyboard",
+            DeviceKind::Mouse => "mouse",
+            DeviceKind::KeyboardMouse => "keyboard+mouse",
+            DeviceKind::Tablet => "tablet",
+            DeviceKind::Touchscreen => "touchscreen",
+            DeviceKind::Touchpad => "touchpad",
+            DeviceKind::Gamepad => "gamepad",
+            DeviceKind::Joystick => "joystick",
+        }
+    }
+
+    /// Look up a kind by its name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    /// Game controllers, which are only recorded in gamepad mode
+    pub fn is_controller(self) -> bool {
+        matches!(self, DeviceKind::Gamepad | DeviceKind::Joystick)
+    }
+
+    /// Devices with absolute axes that playback has to recreate
+    pub fn is_absolute(self) -> bool {
+        matches!(
+            self,
+            DeviceKind::Tablet
+                | DeviceKind::Touchscreen
+                | DeviceKind::Touchpad
+                | DeviceKind::Gamepad
+                | DeviceKind::Joystick
+        )
+    }
+}
+
+impl fmt::Display for DeviceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Criteria for choosing which devices to record from
+///
+/// A device is selected if it matches any of the criteria. An empty filter
+/// selects every keyboard, mouse, tablet, touchscreen and touchpad, or every
+/// game controller in gamepad mode.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceFilter {
+    /// Record game controllers instead of keyboards and mice
+    pub gamepad: bool,
+    /// Device nodes, or symlinks to them such as /dev/input/by-id/...
+    pub paths: Vec<PathBuf>,
+    /// Device name patterns, `*` and `?` are wildcards
+    pub names: Vec<String>,
+    /// USB-style (vendor, product) IDs
+    pub ids: Vec<(u16, u16)>,
+}
+
+impl DeviceFilter {
+    /// Check if no criteria were given
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty() && self.names.is_empty() && self.ids.is_empty()
+    }
+
+    /// Check if the device at `path` should be recorded from
+    pub fn matches(&self, path: &Path, device: &Device) -> bool {
+        if self.is_empty() {
+            return device_kind(device).is_some_and(|kind| kind.is_controller() == self.gamepad);
+        }
+
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let path_matches = self
+            .paths
+            .iter()
+            .any(|p| fs::canonicalize(p).is_ok_and(|p| p == canonical));
+
+        let name = device.name().unwrap_or("");
+        let name_matches = self.names.iter().any(|pattern| glob_match(pattern, name));
+
+        let id = device.input_id();
+        let id_matches = self.ids.contains(&(id.vendor(), id.product()));
+
+        path_matches || name_matches || id_matches
+    }
+}
+
+/// Describe what kind of device this is, if it's one we can record
+pub fn device_kind(device: &Device) -> Option<DeviceKind> {
+    if let Some(kind) = controller_kind(device).or_else(|| absolute_kind(device)) {
+        return Some(kind);
+    }
+
+    // Check if device has typing keys (keyboard) or relative axes (mouse)
+    let has_keys = device.supported_keys().is_some_and(has_typing_keys);
+    let has_relative = device.supported_relative_axes().is_some_and(|axes| axes.iter().len() > 0);
+
+    match (has_keys, has_relative) {
+        (true, true) => Some(DeviceKind::KeyboardMouse),
+        (true, false) => Some(DeviceKind::Keyboard),
+        (false, true) => Some(DeviceKind::Mouse),
+        _ => None,
+    }
+}
+
+/// Check if the keys include letters or Enter, as real keyboards have
+///
+/// Power and sleep buttons, lid switches and video bus devices also report
+/// keys, but none of these.
+fn has_typing_keys(keys: &AttributeSetRef<KeyCode>) -> bool {
+    // The letter rows: Q..P, A..L and Z..M
+    let letters = (16..=25).chain(30..=38).chain(44..=50);
+    keys.contains(KeyCode::KEY_ENTER) || letters.map(KeyCode::new).any(|key| keys.contains(key))
+}
+
+/// Describe a game controller: gamepad or joystick
+fn controller_kind(device: &Device) -> Option<DeviceKind> {
+    let keys = device.supported_keys()?;
+
+    if keys.contains(KeyCode::BTN_SOUTH) {
+        Some(DeviceKind::Gamepad)
+    } else if keys.contains(KeyCode::BTN_TRIGGER) {
+        Some(DeviceKind::Joystick)
+    } else {
+        None
+    }
+}
+
+/// Describe an absolute-axis pointing device: tablet, touchscreen or touchpad
+fn absolute_kind(device: &Device) -> Option<DeviceKind> {
+    let axes = device.supported_absolute_axes()?;
+    let has_position = (axes.contains(AbsoluteAxisCode::ABS_X)
+        && axes.contains(AbsoluteAxisCode::ABS_Y))
+        || (axes.contains(AbsoluteAxisCode::ABS_MT_POSITION_X)
+            && axes.contains(AbsoluteAxisCode::ABS_MT_POSITION_Y));
+    if !has_position {
+        return None;
+    }
+
+    let has_key = |key| device.supported_keys().is_some_and(|keys| keys.contains(key));
+
+    if has_key(KeyCode::BTN_TOOL_PEN) {
+        Some(DeviceKind::Tablet)
+    } else if device.properties().contains(PropType::DIRECT) {
+        Some(DeviceKind::Touchscreen)
+    } else if has_key(KeyCode::BTN_TOOL_FINGER) {
+        Some(DeviceKind::Touchpad)
+    } else {
+        None
+    }
+}
+
+/// List all /dev/input/event* nodes, sorted by number
+pub fn event_devices() -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+
+    for entry in fs::read_dir("/dev/input")? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with("event"))
+        {
+            paths.push(path);
+        }
+    }
+
+    paths.sort_by_key(|path| event_number(path));
+    Ok(paths)
+}
+
+/// Stable symlinks in /dev/input/by-id and /dev/input/by-path pointing at `path`
+pub fn stable_links(path: &Path) -> Vec<PathBuf> {
+    let Ok(target) = fs::canonicalize(path) else {
+        return Vec::new();
+    };
+
+    let mut links = Vec::new();
+    for dir in ["/dev/input/by-id", "/dev/input/by-path"] {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let link = entry.path();
+            if fs::canonicalize(&link).is_ok_and(|p| p == target) {
+                links.push(link);
+            }
+        }
+    }
+
+    links.sort();
+    links
+}
+
+/// Parse a "vendor:product" ID in hex, like "046d:c52b"
+pub fn parse_id(s: &str) -> Result<(u16, u16), String> {
+    let (vendor, product) = s
+        .split_once(':')
+        .ok_or_else(|| format!("Device ID must be VENDOR:PRODUCT: {}", s))?;
+
+    let parse = |part: &str| {
+        u16::from_str_radix(part.trim_start_matches("0x"), 16)
+            .map_err(|_| format!("Invalid hex ID: {}", part))
+    };
+
+    Ok((parse(vendor)?, parse(product)?))
+}
+
+/// Number N of /dev/input/eventN, for sorting
+fn event_number(path: &Path) -> u32 {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_prefix("event"))
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(u32::MAX)
+}
+
+/// Match `text` against a pattern where `*` matches any run of characters
+/// and `?` matches a single character
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text position it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, star_t)) = backtrack {
+            // Let the last `*` swallow one more character
+            p = star + 1;
+            t = star_t + 1;
+            backtrack = Some((star, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evdev::AttributeSet;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("Logitech*", "Logitech USB Receiver"));
+        assert!(glob_match("*Keyboard", "AT Translated Set 2 Keyboard"));
+        assert!(glob_match("*USB*", "Logitech USB Receiver"));
+        assert!(glob_match("event?", "event3"));
+        assert!(glob_match("exact", "exact"));
+        assert!(!glob_match("Logitech*", "Razer Logitech"));
+        assert!(!glob_match("event?", "event12"));
+        assert!(!glob_match("exact", "exactly"));
+    }
+
+    #[test]
+    fn test_parse_id() {
+        assert_eq!(parse_id("046d:c52b").unwrap(), (0x046d, 0xc52b));
+        assert_eq!(parse_id("0x046D:0xC52B").unwrap(), (0x046d, 0xc52b));
+        assert!(parse_id("046d").is_err());
+        assert!(parse_id("xyz:c52b").is_err());
+    }
+
+    #[test]
+    fn test_has_typing_keys() {
+        let keys = |codes: &[KeyCode]| codes.iter().copied().collect::<AttributeSet<KeyCode>>();
+
+        assert!(has_typing_keys(&keys(&[KeyCode::KEY_ESC, KeyCode::KEY_Q])));
+        assert!(has_typing_keys(&keys(&[KeyCode::KEY_KPENTER, KeyCode::KEY_ENTER])));
+        // Power Button, Sleep Button and Video Bus
+        assert!(!has_typing_keys(&keys(&[KeyCode::KEY_POWER])));
+        assert!(!has_typing_keys(&keys(&[KeyCode::KEY_SLEEP])));
+        assert!(!has_typing_keys(&keys(&[
+            KeyCode::KEY_BRIGHTNESSDOWN,
+            KeyCode::KEY_BRIGHTNESSUP,
+            KeyCode::KEY_SWITCHVIDEOMODE,
+        ])));
+        // Mouse buttons
+        assert!(!has_typing_keys(&keys(&[KeyCode::BTN_LEFT, KeyCode::BTN_RIGHT])));
+    }
+
+    #[test]
+    fn test_device_kind_names() {
+        for kind in DeviceKind::ALL {
+            assert_eq!(DeviceKind::from_name(kind.name()), Some(kind));
+        }
+        assert_eq!(DeviceKind::from_name("toaster"), None);
+        assert!(DeviceKind::Gamepad.is_controller());
+        assert!(DeviceKind::Gamepad.is_absolute());
+        assert!(!DeviceKind::Touchpad.is_controller());
+    }
+
+    #[test]
+    fn test_event_number() {
+        assert_eq!(event_number(Path::new("/dev/input/event12")), 12);
+        assert_eq!(event_number(Path::new("/dev/input/mice")), u32::MAX);
+    }
+}
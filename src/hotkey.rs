@@ -0,0 +1,100 @@
+//! Hotkey chords such as `F1` or `CTRL+ALT+R`
+//!
+//! Key names are resolved through the same keymap as the macro DSL.
+
+use crate::keymap;
+use std::collections::HashSet;
+use std::fmt;
+
+/// A chord of one or more keys that triggers an action when completed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hotkey {
+    /// Keys in the chord, in the order they were written
+    keys: Vec<u16>,
+}
+
+impl Hotkey {
+    /// Parse a chord like "F1" or "CTRL+ALT+R"
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut keys = Vec::new();
+
+        for name in s.split('+') {
+            let name = name.trim();
+            match keymap::name_to_keycode(name) {
+                Some(code) if !keys.contains(&code) => keys.push(code),
+                Some(_) => return Err(format!("Duplicate key in hotkey: {}", name)),
+                None => return Err(format!("Unknown key: {}", name)),
+            }
+        }
+
+        Ok(Self { keys })
+    }
+
+    /// Keycodes that make up this chord
+    pub fn keys(&self) -> &[u16] {
+        &self.keys
+    }
+
+    /// Check whether pressing `key` completes the chord, given the keys held
+    /// down (including `key` itself)
+    pub fn is_triggered(&self, key: u16, held: &HashSet<u16>) -> bool {
+        self.keys.contains(&key) && self.keys.iter().all(|code| held.contains(code))
+    }
+}
+
+impl Default for Hotkey {
+    fn default() -> Self {
+        Self {
+            keys: vec![59], // F1
+        }
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self
+            .keys
+            .iter()
+            .map(|&code| keymap::keycode_to_name(code))
+            .collect();
+        write!(f, "{}", names.join("+"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_single_key() {
+        let hotkey = Hotkey::parse("F1").unwrap();
+        assert_eq!(hotkey.keys(), &[59]);
+        assert_eq!(hotkey, Hotkey::default());
+    }
+
+    #[test]
+    fn test_parse_chord() {
+        let hotkey = Hotkey::parse("ctrl+alt+r").unwrap();
+        assert_eq!(hotkey.keys(), &[29, 56, 19]);
+        assert_eq!(hotkey.to_string(), "CTRL+ALT+R");
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Hotkey::parse("CTRL+NOPE").is_err());
+        assert!(Hotkey::parse("").is_err());
+        assert!(Hotkey::parse("R+R").is_err());
+    }
+
+    #[test]
+    fn test_is_triggered() {
+        let hotkey = Hotkey::parse("CTRL+ALT+R").unwrap();
+
+        let held: HashSet<u16> = [29, 19].into_iter().collect();
+        assert!(!hotkey.is_triggered(19, &held));
+
+        let held: HashSet<u16> = [29, 56, 19].into_iter().collect();
+        assert!(hotkey.is_triggered(19, &held));
+        assert!(!hotkey.is_triggered(30, &held));
+    }
+}
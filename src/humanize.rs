@@ -0,0 +1,220 @@
+//! Making playback look less mechanical
+//!
+//! Some applications notice input that is perfectly regular, so humanize
+//! mode randomly stretches and shrinks the gaps between events and wobbles
+//! mouse movement. The randomness comes from a seeded generator, so a run
+//! can be reproduced exactly by passing the same seed again.
+
+use crate::recorder::RecordedEvent;
+use evdev::{EventType, InputEvent, RelativeAxisCode};
+
+/// Small seedable random number generator (SplitMix64)
+///
+/// Not suitable for anything security related, but fast, tiny and the
+/// same on every platform, which is what reproducible runs need.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform float in [-bound, bound]
+    pub fn spread(&mut self, bound: f64) -> f64 {
+        // 53 random bits give every representable value in [0, 1)
+        let unit = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        (unit * 2.0 - 1.0) * bound
+    }
+
+    /// Uniform integer in [-bound, bound]
+    pub fn spread_int(&mut self, bound: i32) -> i32 {
+        if bound <= 0 {
+            return 0;
+        }
+        let span = 2 * bound as u64 + 1;
+        (self.next_u64() % span) as i32 - bound
+    }
+}
+
+/// Random variation applied to a macro each time it is played
+#[derive(Debug, Clone)]
+pub struct Humanize {
+    /// Fraction each gap between events may grow or shrink by, 0.1 = ±10%
+    /// (applies to key holds and waits alike)
+    pub time_jitter: f64,
+    /// Pixels the pointer may stray from the recorded path on each move
+    pub mouse_jitter: i32,
+    rng: Rng,
+}
+
+impl Humanize {
+    pub fn new(time_jitter: f64, mouse_jitter: i32, seed: u64) -> Self {
+        Self {
+            time_jitter,
+            mouse_jitter,
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// Produce a randomized copy of the events
+    ///
+    /// Events sharing a timestamp keep sharing it, so frames stay intact and
+    /// their order never changes. Mouse noise is undone on the next move, so
+    /// the pointer wobbles around the recorded path instead of drifting away
+    /// from it, and the last move on each axis ends exactly on the path so
+    /// repeated runs don't drift either.
+    pub fn apply(&mut self, events: &[RecordedEvent]) -> Vec<RecordedEvent> {
+        let mut result = Vec::with_capacity(events.len());
+        let mut last_original = 0u64;
+        let mut timestamp_us = 0u64;
+        // Offset from the recorded pointer position, per axis
+        let mut offset = (0i32, 0i32);
+        let last_move = |axis: RelativeAxisCode| {
+            events.iter().rposition(|recorded| {
+                recorded.event.event_type() == EventType::RELATIVE
+                    && recorded.event.code() == axis.0
+                    && recorded.event.value() != 0
+            })
+        };
+        let last_moves = (
+            last_move(RelativeAxisCode::REL_X),
+            last_move(RelativeAxisCode::REL_Y),
+        );
+
+        for (index, recorded) in events.iter().enumerate() {
+            let gap = recorded.timestamp_us.saturating_sub(last_original);
+            if gap > 0 {
+                let factor = 1.0 + self.rng.spread(self.time_jitter);
+                timestamp_us += (gap as f64 * factor).round() as u64;
+                last_original = recorded.timestamp_us;
+            }
+
+            let mut event = recorded.event;
+            if event.event_type() == EventType::RELATIVE && event.value() != 0 {
+                let axis = match RelativeAxisCode(event.code()) {
+                    RelativeAxisCode::REL_X => Some((&mut offset.0, last_moves.0)),
+                    RelativeAxisCode::REL_Y => Some((&mut offset.1, last_moves.1)),
+                    _ => None,
+                };
+                if let Some((axis_offset, last_move)) = axis {
+                    let new_offset = if last_move == Some(index) {
+                        0
+                    } else {
+                        self.rng.spread_int(self.mouse_jitter)
+                    };
+                    let value = event.value() + new_offset - *axis_offset;
+                    *axis_offset = new_offset;
+                    event = InputEvent::new(event.event_type().0, event.code(), value);
+                }
+            }
+
+            result.push(RecordedEvent { timestamp_us, event });
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(timestamp_us: u64, event_type: EventType, code: u16, value: i32) -> RecordedEvent {
+        RecordedEvent {
+            timestamp_us,
+            event: InputEvent::new(event_type.0, code, value),
+        }
+    }
+
+    #[test]
+    fn test_same_seed_same_result() {
+        let events = vec![
+            event(0, EventType::KEY, 17, 1),
+            event(100_000, EventType::KEY, 17, 0),
+            event(100_000, EventType::RELATIVE, 0, 20),
+            event(300_000, EventType::RELATIVE, 1, -20),
+        ];
+
+        let a = Humanize::new(0.2, 3, 42).apply(&events);
+        let b = Humanize::new(0.2, 3, 42).apply(&events);
+        let c = Humanize::new(0.2, 3, 43).apply(&events);
+
+        let values = |events: &[RecordedEvent]| {
+            events
+                .iter()
+                .map(|e| (e.timestamp_us, e.event.value()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(values(&a), values(&b));
+        assert_ne!(values(&a), values(&c));
+    }
+
+    #[test]
+    fn test_stays_within_bounds() {
+        let mut events = Vec::new();
+        for i in 0..100 {
+            events.push(event(i * 10_000, EventType::RELATIVE, 0, 10));
+            events.push(event(i * 10_000, EventType::SYNCHRONIZATION, 0, 0));
+        }
+
+        let humanized = Humanize::new(0.1, 2, 7).apply(&events);
+
+        let mut x = 0;
+        for pair in humanized.chunks(2) {
+            // Frames keep a single timestamp
+            assert_eq!(pair[0].timestamp_us, pair[1].timestamp_us);
+            x += pair[0].event.value();
+        }
+        // The pointer ends up exactly at the recorded position
+        assert_eq!(x, 1000);
+
+        for window in humanized.windows(3).step_by(2) {
+            let gap = window[2].timestamp_us - window[0].timestamp_us;
+            assert!((9_000..=11_000).contains(&gap), "gap {}", gap);
+        }
+    }
+
+    #[test]
+    fn test_no_drift_across_runs() {
+        let events = vec![
+            event(0, EventType::RELATIVE, 0, 5),
+            event(0, EventType::RELATIVE, 1, -3),
+            event(10_000, EventType::RELATIVE, 0, 5),
+            event(20_000, EventType::RELATIVE, 1, -3),
+            event(20_000, EventType::RELATIVE, 8, 1), // wheel
+        ];
+
+        let mut humanize = Humanize::new(0.1, 2, 3);
+        let (mut x, mut y) = (0, 0);
+        for _ in 0..50 {
+            for recorded in humanize.apply(&events) {
+                match RelativeAxisCode(recorded.event.code()) {
+                    RelativeAxisCode::REL_X => x += recorded.event.value(),
+                    RelativeAxisCode::REL_Y => y += recorded.event.value(),
+                    _ => {}
+                }
+            }
+        }
+        assert_eq!((x, y), (10 * 50, -6 * 50));
+    }
+
+    #[test]
+    fn test_spread_int_bounds() {
+        let mut rng = Rng::new(1);
+        for _ in 0..1000 {
+            assert!((-3..=3).contains(&rng.spread_int(3)));
+        }
+        assert_eq!(rng.spread_int(0), 0);
+    }
+}
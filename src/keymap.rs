@@ -4,16 +4,53 @@
 
 use std::collections::HashMap;
 
+/// Highest keycode the kernel knows about (KEY_MAX)
+const KEY_MAX: u16 = 0x2ff;
+
+/// Highest absolute axis the kernel knows about (ABS_MAX)
+const ABS_MAX: u16 = 0x3f;
+
 /// Get human-readable name for a Linux keycode (QWERTY layout)
-pub fn keycode_to_name(keycode: u16) -> Option<String> {
+///
+/// Keycodes without a name are written as `KEY_<code>`, which
+/// `name_to_keycode` reads back.
+pub fn keycode_to_name(keycode: u16) -> String {
     let map = get_qwerty_map();
-    map.get(&keycode).map(|s| s.to_string())
+    match map.get(&keycode) {
+        Some(name) => name.to_string(),
+        None => format!("KEY_{}", keycode),
+    }
 }
 
-/// Get Linux keycode from human-readable name (QWERTY layout)
+/// Get Linux keycode from human-readable name (QWERTY layout) or `KEY_<code>`
 pub fn name_to_keycode(name: &str) -> Option<u16> {
+    let name = name.to_uppercase();
     let map = get_qwerty_reverse_map();
-    map.get(name.to_uppercase().as_str()).copied()
+    map.get(name.as_str()).copied().or_else(|| {
+        name.strip_prefix("KEY_")
+            .and_then(|code| code.parse().ok())
+            .filter(|&code| code <= KEY_MAX)
+    })
+}
+
+/// Check if a keycode is a mouse button (BTN_LEFT to BTN_TASK)
+///
+/// Touchpads and tablets report some of these too, but they always belong to
+/// the virtual mouse on playback.
+pub fn is_mouse_button(keycode: u16) -> bool {
+    matches!(keycode, 0x110..=0x117)
+}
+
+/// Check if a keycode is a modifier (CTRL, SHIFT, ALT or META, either side)
+pub fn is_modifier(keycode: u16) -> bool {
+    matches!(keycode, 29 | 42 | 54 | 56 | 97 | 100 | 125 | 126)
+}
+
+/// Check if a keycode is a mouse, joystick, gamepad, tablet or touch button
+/// rather than a key (BTN_MISC to BTN_GEAR_UP, BTN_DPAD_* and
+/// BTN_TRIGGER_HAPPY*)
+pub fn is_button(keycode: u16) -> bool {
+    matches!(keycode, 0x100..=0x15f | 0x220..=0x223 | 0x2c0..=0x2ff)
 }
 
 /// QWERTY layout keycode to name mapping
@@ -88,6 +125,8 @@ fn get_qwerty_map() -> HashMap<u16, &'static str> {
         (58, "CAPSLOCK"),
         (97, "RIGHTCTRL"),
         (100, "RIGHTALT"),
+        (125, "META"),
+        (126, "RIGHTMETA"),
 
         // Navigation
         (102, "HOME"),
@@ -130,10 +169,69 @@ fn get_qwerty_map() -> HashMap<u16, &'static str> {
         (96, "KPENTER"),
         (98, "KPSLASH"),
 
-        // Mouse buttons (for completeness)
+        // Mouse buttons
         (272, "BTN_LEFT"),
         (273, "BTN_RIGHT"),
         (274, "BTN_MIDDLE"),
+        (275, "BTN_SIDE"),
+        (276, "BTN_EXTRA"),
+        (277, "BTN_FORWARD"),
+        (278, "BTN_BACK"),
+        (279, "BTN_TASK"),
+
+        // Joystick buttons
+        (288, "BTN_TRIGGER"),
+        (289, "BTN_THUMB"),
+        (290, "BTN_THUMB2"),
+        (291, "BTN_TOP"),
+        (292, "BTN_TOP2"),
+        (293, "BTN_PINKIE"),
+        (294, "BTN_BASE"),
+        (295, "BTN_BASE2"),
+        (296, "BTN_BASE3"),
+        (297, "BTN_BASE4"),
+        (298, "BTN_BASE5"),
+        (299, "BTN_BASE6"),
+        (303, "BTN_DEAD"),
+
+        // Gamepad buttons
+        (304, "BTN_SOUTH"),
+        (305, "BTN_EAST"),
+        (306, "BTN_C"),
+        (307, "BTN_NORTH"),
+        (308, "BTN_WEST"),
+        (309, "BTN_Z"),
+        (310, "BTN_TL"),
+        (311, "BTN_TR"),
+        (312, "BTN_TL2"),
+        (313, "BTN_TR2"),
+        (314, "BTN_SELECT"),
+        (315, "BTN_START"),
+        (316, "BTN_MODE"),
+        (317, "BTN_THUMBL"),
+        (318, "BTN_THUMBR"),
+        (544, "BTN_DPAD_UP"),
+        (545, "BTN_DPAD_DOWN"),
+        (546, "BTN_DPAD_LEFT"),
+        (547, "BTN_DPAD_RIGHT"),
+
+        // Tablet, touchscreen and touchpad buttons
+        (320, "BTN_TOOL_PEN"),
+        (321, "BTN_TOOL_RUBBER"),
+        (322, "BTN_TOOL_BRUSH"),
+        (323, "BTN_TOOL_PENCIL"),
+        (324, "BTN_TOOL_AIRBRUSH"),
+        (325, "BTN_TOOL_FINGER"),
+        (326, "BTN_TOOL_MOUSE"),
+        (327, "BTN_TOOL_LENS"),
+        (328, "BTN_TOOL_QUINTTAP"),
+        (329, "BTN_STYLUS3"),
+        (330, "BTN_TOUCH"),
+        (331, "BTN_STYLUS"),
+        (332, "BTN_STYLUS2"),
+        (333, "BTN_TOOL_DOUBLETAP"),
+        (334, "BTN_TOOL_TRIPLETAP"),
+        (335, "BTN_TOOL_QUADTAP"),
     ])
 }
 
@@ -142,15 +240,135 @@ fn get_qwerty_reverse_map() -> HashMap<&'static str, u16> {
     get_qwerty_map().into_iter().map(|(k, v)| (v, k)).collect()
 }
 
+/// Get the DSL name of an absolute axis (ABS_* without the prefix)
+///
+/// Axes without a name, such as the extra axes hid-input gives many
+/// joysticks, are written as `ABS_<code>`, which `name_to_abs_axis` reads
+/// back.
+pub fn abs_axis_to_name(code: u16) -> String {
+    match ABS_AXES.iter().find(|(c, _)| *c == code) {
+        Some((_, name)) => name.to_string(),
+        None => format!("ABS_{}", code),
+    }
+}
+
+/// Get an absolute axis code from its DSL name, e.g. "X" or "MT_SLOT", or
+/// `ABS_<code>`
+pub fn name_to_abs_axis(name: &str) -> Option<u16> {
+    let name = name.to_uppercase();
+    ABS_AXES.iter().find(|(_, n)| *n == name).map(|(code, _)| *code).or_else(|| {
+        name.strip_prefix("ABS_")
+            .and_then(|code| code.parse().ok())
+            .filter(|&code| code <= ABS_MAX)
+    })
+}
+
+/// Get the DSL name of a mouse button in `click`, `drag` and the like
+pub fn mouse_button_to_name(code: u16) -> Option<&'static str> {
+    MOUSE_BUTTONS.iter().find(|(c, _)| *c == code).map(|(_, name)| *name)
+}
+
+/// Get a mouse button code from its DSL name, e.g. "left", or BTN_* name
+pub fn name_to_mouse_button(name: &str) -> Option<u16> {
+    let lower = name.to_lowercase();
+    MOUSE_BUTTONS
+        .iter()
+        .find(|(_, n)| *n == lower)
+        .map(|(code, _)| *code)
+        .or_else(|| name_to_keycode(name).filter(|code| mouse_button_to_name(*code).is_some()))
+}
+
+/// Get the DSL name of an input property (INPUT_PROP_* without the prefix)
+pub fn property_to_name(code: u16) -> Option<&'static str> {
+    PROPERTIES.iter().find(|(c, _)| *c == code).map(|(_, name)| *name)
+}
+
+/// Get an input property code from its DSL name, e.g. "DIRECT"
+pub fn name_to_property(name: &str) -> Option<u16> {
+    let name = name.to_uppercase();
+    PROPERTIES.iter().find(|(_, n)| *n == name).map(|(code, _)| *code)
+}
+
+/// Mouse button codes and names (BTN_* without the prefix, lowercase)
+const MOUSE_BUTTONS: &[(u16, &str)] = &[
+    (272, "left"),
+    (273, "right"),
+    (274, "middle"),
+    (275, "side"),
+    (276, "extra"),
+    (277, "forward"),
+    (278, "back"),
+    (279, "task"),
+];
+
+/// Absolute axis codes and names
+const ABS_AXES: &[(u16, &str)] = &[
+    (0x00, "X"),
+    (0x01, "Y"),
+    (0x02, "Z"),
+    (0x03, "RX"),
+    (0x04, "RY"),
+    (0x05, "RZ"),
+    (0x06, "THROTTLE"),
+    (0x07, "RUDDER"),
+    (0x08, "WHEEL"),
+    (0x09, "GAS"),
+    (0x0a, "BRAKE"),
+    (0x10, "HAT0X"),
+    (0x11, "HAT0Y"),
+    (0x12, "HAT1X"),
+    (0x13, "HAT1Y"),
+    (0x14, "HAT2X"),
+    (0x15, "HAT2Y"),
+    (0x16, "HAT3X"),
+    (0x17, "HAT3Y"),
+    (0x18, "PRESSURE"),
+    (0x19, "DISTANCE"),
+    (0x1a, "TILT_X"),
+    (0x1b, "TILT_Y"),
+    (0x1c, "TOOL_WIDTH"),
+    (0x20, "VOLUME"),
+    (0x21, "PROFILE"),
+    (0x28, "MISC"),
+    // Multitouch (protocol B uses MT_SLOT to select the contact)
+    (0x2f, "MT_SLOT"),
+    (0x30, "MT_TOUCH_MAJOR"),
+    (0x31, "MT_TOUCH_MINOR"),
+    (0x32, "MT_WIDTH_MAJOR"),
+    (0x33, "MT_WIDTH_MINOR"),
+    (0x34, "MT_ORIENTATION"),
+    (0x35, "MT_POSITION_X"),
+    (0x36, "MT_POSITION_Y"),
+    (0x37, "MT_TOOL_TYPE"),
+    (0x38, "MT_BLOB_ID"),
+    (0x39, "MT_TRACKING_ID"),
+    (0x3a, "MT_PRESSURE"),
+    (0x3b, "MT_DISTANCE"),
+    (0x3c, "MT_TOOL_X"),
+    (0x3d, "MT_TOOL_Y"),
+];
+
+/// Input property codes and names
+const PROPERTIES: &[(u16, &str)] = &[
+    (0x00, "POINTER"),
+    (0x01, "DIRECT"),
+    (0x02, "BUTTONPAD"),
+    (0x03, "SEMI_MT"),
+    (0x04, "TOPBUTTONPAD"),
+    (0x05, "POINTING_STICK"),
+    (0x06, "ACCELEROMETER"),
+];
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_keycode_to_name() {
-        assert_eq!(keycode_to_name(17), Some("W".to_string()));
-        assert_eq!(keycode_to_name(30), Some("A".to_string()));
-        assert_eq!(keycode_to_name(57), Some("SPACE".to_string()));
+        assert_eq!(keycode_to_name(17), "W");
+        assert_eq!(keycode_to_name(30), "A");
+        assert_eq!(keycode_to_name(57), "SPACE");
+        assert_eq!(keycode_to_name(0x2c0), "KEY_704"); // BTN_TRIGGER_HAPPY1
     }
 
     #[test]
@@ -159,12 +377,40 @@ mod tests {
         assert_eq!(name_to_keycode("w"), Some(17)); // Case insensitive
         assert_eq!(name_to_keycode("SPACE"), Some(57));
         assert_eq!(name_to_keycode("INVALID"), None);
+        assert_eq!(name_to_keycode("key_704"), Some(0x2c0));
+        assert_eq!(name_to_keycode("KEY_768"), None);
+        assert_eq!(name_to_keycode("KEY_"), None);
+    }
+
+    #[test]
+    fn test_abs_axis_names() {
+        assert_eq!(abs_axis_to_name(0x18), "PRESSURE");
+        assert_eq!(abs_axis_to_name(0x29), "ABS_41");
+        assert_eq!(name_to_abs_axis("mt_slot"), Some(0x2f));
+        assert_eq!(name_to_abs_axis("abs_41"), Some(0x29));
+        assert_eq!(name_to_abs_axis("ABS_64"), None);
+        assert_eq!(name_to_abs_axis("NOPE"), None);
+        assert_eq!(name_to_keycode("BTN_TOUCH"), Some(330));
+        assert_eq!(name_to_keycode("BTN_SOUTH"), Some(304));
+        assert!(is_mouse_button(272) && is_mouse_button(279));
+        assert!(!is_mouse_button(330));
+    }
+
+    #[test]
+    fn test_mouse_button_names() {
+        assert_eq!(name_to_keycode("BTN_SIDE"), Some(275));
+        assert_eq!(mouse_button_to_name(276), Some("extra"));
+        assert_eq!(name_to_mouse_button("Right"), Some(273));
+        assert_eq!(name_to_mouse_button("BTN_MIDDLE"), Some(274));
+        assert_eq!(name_to_mouse_button("BTN_TOUCH"), None);
+        assert_eq!(name_to_mouse_button("W"), None);
     }
 
     #[test]
     fn test_roundtrip() {
-        let keycode = 17;
-        let name = keycode_to_name(keycode).unwrap();
-        assert_eq!(name_to_keycode(&name), Some(keycode));
+        for keycode in [17, 0x2c0, 0x2e7] {
+            let name = keycode_to_name(keycode);
+            assert_eq!(name_to_keycode(&name), Some(keycode));
+        }
     }
 }
@@ -1,17 +1,38 @@
 use std::env;
 use std::error::Error;
+use std::io::{self, Write};
 use std::path::Path;
-use std::thread;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 mod recorder;
 mod player;
 mod storage;
 mod state;
 mod keymap;
-
-use recorder::Recorder;
-use player::Player;
+mod hotkey;
+mod config;
+mod devices;
+mod signals;
+mod profile;
+mod humanize;
+mod monitor;
+mod repeat;
+mod autorepeat;
+
+use autorepeat::KeyRepeat;
+use config::Config;
+use devices::{DeviceFilter, DeviceKind};
+use hotkey::Hotkey;
+use humanize::Humanize;
+use recorder::{RecordHotkeys, Recorder, Transition};
+use monitor::{Control, InputMonitor, PlayHotkeys, YieldMode};
+use player::{Outcome, Player, Timing};
+use repeat::{Repeat, Runs};
+use state::MouseFilter;
+use storage::Format;
+
+/// Name of the virtual devices used for playback
+const PLAYBACK_DEVICE: &str = "evkey-playback";
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = env::args().collect();
@@ -23,31 +44,337 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     match args[1].as_str() {
         "record" => {
-            if args.len() < 3 {
-                eprintln!("Usage: evkey record <output_file>");
+            let mut rest = args[2..].to_vec();
+            let config = Config::load()?;
+
+            let mut hotkeys = config.record_hotkeys;
+
+            let options = [
+                "--hotkey",
+                "--start-hotkey",
+                "--stop-hotkey",
+                "--pause-hotkey",
+                "--discard-hotkey",
+            ];
+            for option in options {
+                let Some(keys) = take_option(&mut rest, option)? else {
+                    continue;
+                };
+                let hotkey = match Hotkey::parse(&keys) {
+                    Ok(hotkey) => hotkey,
+                    Err(e) => {
+                        eprintln!("Error: Invalid {}: {}", option, e);
+                        return Ok(());
+                    }
+                };
+                match option {
+                    "--hotkey" => {
+                        hotkeys.start = hotkey.clone();
+                        hotkeys.stop = hotkey;
+                    }
+                    "--start-hotkey" => hotkeys.start = hotkey,
+                    "--stop-hotkey" => hotkeys.stop = hotkey,
+                    "--pause-hotkey" => hotkeys.pause = hotkey,
+                    _ => hotkeys.discard = hotkey,
+                }
+            }
+
+            let mut filter = DeviceFilter {
+                gamepad: take_flag(&mut rest, "--gamepad"),
+                paths: take_options(&mut rest, "--device")?.into_iter().map(Into::into).collect(),
+                names: take_options(&mut rest, "--device-name")?,
+                ids: Vec::new(),
+            };
+            for id in take_options(&mut rest, "--device-id")? {
+                match devices::parse_id(&id) {
+                    Ok(id) => filter.ids.push(id),
+                    Err(e) => {
+                        eprintln!("Error: Invalid --device-id: {}", e);
+                        return Ok(());
+                    }
+                }
+            }
+
+            let grab = take_flag(&mut rest, "--grab");
+            let raw = take_flag(&mut rest, "--raw");
+
+            let mut mouse_filter = config.mouse_filter;
+            if let Some(pixels) = take_option(&mut rest, "--mouse-threshold")? {
+                match pixels.parse::<i32>() {
+                    Ok(pixels) if pixels >= 0 => mouse_filter.threshold = pixels,
+                    _ => {
+                        eprintln!("Error: Invalid --mouse-threshold: {}", pixels);
+                        return Ok(());
+                    }
+                }
+            }
+            if let Some(pixels) = take_option(&mut rest, "--simplify")? {
+                match pixels.parse::<f64>() {
+                    Ok(pixels) if pixels.is_finite() && pixels >= 0.0 => {
+                        mouse_filter.simplify = Some(pixels);
+                    }
+                    _ => {
+                        eprintln!("Error: Invalid --simplify: {}", pixels);
+                        return Ok(());
+                    }
+                }
+            }
+
+            let mut quantize = config.quantize.unwrap_or(Duration::from_millis(1));
+            if let Some(value) = take_option(&mut rest, "--quantize")? {
+                match storage::parse_duration(&value) {
+                    Ok(value) => quantize = value,
+                    Err(e) => {
+                        eprintln!("Error: Invalid --quantize: {}", e);
+                        return Ok(());
+                    }
+                }
+            }
+
+            let options = RecordOptions {
+                hotkeys,
+                filter,
+                grab,
+                raw,
+                mouse_filter,
+                quantize,
+            };
+
+            if let Some(option) = rest.iter().find(|arg| arg.starts_with("--")) {
+                eprintln!("Error: Unknown option: {}", option);
                 return Ok(());
             }
-            record_macro(&args[2])?;
+
+            match rest.first() {
+                Some(file) => record_macro(file, options)?,
+                None => {
+                    eprintln!("Usage: evkey record [options] <output_file>");
+                    return Ok(());
+                }
+            }
         }
         "play" => {
-            if args.len() < 3 {
-                eprintln!("Usage: evkey play [--loop] <input_file>");
+            let mut rest = args[2..].to_vec();
+            let config = Config::load()?;
+
+            let mut repeat = Repeat::default();
+            if take_flag(&mut rest, "--loop") {
+                repeat.runs = Some(Runs::Forever);
+            }
+            if let Some(runs) = take_option(&mut rest, "--repeat")? {
+                match repeat::parse_runs(&runs) {
+                    Ok(runs) => repeat.runs = Some(runs),
+                    Err(e) => {
+                        eprintln!("Error: Invalid --repeat: {}", e);
+                        return Ok(());
+                    }
+                }
+            }
+            if let Some(interval) = take_option(&mut rest, "--interval")? {
+                match storage::parse_duration(&interval) {
+                    Ok(interval) => repeat.interval = Some(interval),
+                    Err(e) => {
+                        eprintln!("Error: Invalid --interval: {}", e);
+                        return Ok(());
+                    }
+                }
+            }
+            if let Some(until) = take_option(&mut rest, "--until")? {
+                match repeat::parse_time_of_day(&until) {
+                    Ok(time) => repeat.until = Some(time),
+                    Err(e) => {
+                        eprintln!("Error: Invalid --until: {}", e);
+                        return Ok(());
+                    }
+                }
+            }
+
+            let mut hotkeys = config.play_hotkeys;
+            for option in ["--abort-hotkey", "--play-pause-hotkey"] {
+                let Some(keys) = take_option(&mut rest, option)? else {
+                    continue;
+                };
+                let hotkey = match Hotkey::parse(&keys) {
+                    Ok(hotkey) => hotkey,
+                    Err(e) => {
+                        eprintln!("Error: Invalid {}: {}", option, e);
+                        return Ok(());
+                    }
+                };
+                match option {
+                    "--abort-hotkey" => hotkeys.abort = hotkey,
+                    _ => hotkeys.pause = hotkey,
+                }
+            }
+
+            let mut timing = Timing::default();
+            if let Some(speed) = take_option(&mut rest, "--speed")? {
+                match speed.parse::<f64>() {
+                    Ok(speed) if speed.is_finite() && speed >= player::MIN_SPEED => {
+                        timing.speed = speed;
+                    }
+                    _ => {
+                        eprintln!(
+                            "Error: Invalid --speed: {} (must be at least {})",
+                            speed,
+                            player::MIN_SPEED
+                        );
+                        return Ok(());
+                    }
+                }
+            }
+            if let Some(max_wait) = take_option(&mut rest, "--max-wait")? {
+                match storage::parse_duration(&max_wait) {
+                    Ok(max_wait) => timing.max_wait = Some(max_wait),
+                    Err(e) => {
+                        eprintln!("Error: Invalid --max-wait: {}", e);
+                        return Ok(());
+                    }
+                }
+            }
+
+            let humanize_enabled = take_flag(&mut rest, "--humanize");
+            let time_jitter = match take_option(&mut rest, "--humanize-time")? {
+                Some(percent) => match parse_percent(&percent) {
+                    Some(fraction) if fraction < 1.0 => Some(fraction),
+                    _ => {
+                        eprintln!("Error: Invalid --humanize-time: {}", percent);
+                        return Ok(());
+                    }
+                },
+                None => None,
+            };
+            let mouse_jitter = match take_option(&mut rest, "--humanize-mouse")? {
+                Some(pixels) => match pixels.parse::<i32>() {
+                    Ok(pixels) if pixels >= 0 => Some(pixels),
+                    _ => {
+                        eprintln!("Error: Invalid --humanize-mouse: {}", pixels);
+                        return Ok(());
+                    }
+                },
+                None => None,
+            };
+            let seed = match take_option(&mut rest, "--seed")? {
+                Some(seed) => match seed.parse::<u64>() {
+                    Ok(seed) => Some(seed),
+                    Err(_) => {
+                        eprintln!("Error: Invalid --seed: {}", seed);
+                        return Ok(());
+                    }
+                },
+                None => None,
+            };
+            let humanize = if humanize_enabled {
+                let seed = seed.unwrap_or_else(random_seed);
+                let humanize =
+                    Humanize::new(time_jitter.unwrap_or(0.1), mouse_jitter.unwrap_or(2), seed);
+                Some((humanize, seed))
+            } else if time_jitter.is_some() || mouse_jitter.is_some() || seed.is_some() {
+                eprintln!("Error: --humanize-time, --humanize-mouse and --seed need --humanize");
                 return Ok(());
+            } else {
+                None
+            };
+
+            let yield_pause = take_flag(&mut rest, "--yield-pause");
+            let yield_mode = match (take_flag(&mut rest, "--yield-to-user"), yield_pause) {
+                (true, true) => Some(YieldMode::Pause),
+                (true, false) => Some(YieldMode::Abort),
+                (false, _) => None,
+            };
+
+            let mut start_on = None;
+            if let Some(keys) = take_option(&mut rest, "--start-on")? {
+                match Hotkey::parse(&keys) {
+                    Ok(hotkey) => start_on = Some(hotkey),
+                    Err(e) => {
+                        eprintln!("Error: Invalid --start-on: {}", e);
+                        return Ok(());
+                    }
+                }
+            }
+            // Waiting for a key gives the user all the time they need already
+            let mut delay = if start_on.is_some() {
+                Duration::ZERO
+            } else {
+                Duration::from_secs(3)
+            };
+            if let Some(value) = take_option(&mut rest, "--delay")? {
+                match storage::parse_duration(&value) {
+                    Ok(value) => delay = value,
+                    Err(e) => {
+                        eprintln!("Error: Invalid --delay: {}", e);
+                        return Ok(());
+                    }
+                }
             }
 
-            // Find the input file (first arg that isn't --loop)
-            let input_file = args[2..]
-                .iter()
-                .find(|arg| arg.as_str() != "--loop")
-                .map(|s| s.as_str());
+            let mut key_repeat = match take_option(&mut rest, "--key-repeat")?.as_deref() {
+                None => None,
+                Some("recorded") => Some(KeyRepeat::Recorded),
+                Some("synthesize") => Some(KeyRepeat::synthesized()),
+                Some("off") => Some(KeyRepeat::Off),
+                Some(other) => {
+                    eprintln!(
+                        "Error: Invalid --key-repeat: {} (use recorded, synthesize or off)",
+                        other
+                    );
+                    return Ok(());
+                }
+            };
+            let mut repeat_timing = [None, None];
+            for (option, value) in ["--key-repeat-delay", "--key-repeat-period"]
+                .into_iter()
+                .zip(&mut repeat_timing)
+            {
+                let Some(duration) = take_option(&mut rest, option)? else {
+                    continue;
+                };
+                match storage::parse_duration(&duration) {
+                    Ok(duration) if !duration.is_zero() => *value = Some(duration),
+                    Ok(_) => {
+                        eprintln!("Error: Invalid {}: must be longer than 0", option);
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        eprintln!("Error: Invalid {}: {}", option, e);
+                        return Ok(());
+                    }
+                }
+            }
+            // Giving the timing implies generated repeats
+            if repeat_timing.iter().any(Option::is_some) {
+                let (delay, period) = match key_repeat {
+                    Some(KeyRepeat::Synthesized { delay, period }) => (delay, period),
+                    _ => (autorepeat::DEFAULT_DELAY, autorepeat::DEFAULT_PERIOD),
+                };
+                key_repeat = Some(KeyRepeat::Synthesized {
+                    delay: repeat_timing[0].unwrap_or(delay),
+                    period: repeat_timing[1].unwrap_or(period),
+                });
+            }
 
-            let loop_flag = args.iter().any(|a| a == "--loop");
+            let options = PlayOptions {
+                delay,
+                start_on,
+                repeat,
+                timing,
+                humanize,
+                hotkeys,
+                yield_mode,
+                key_repeat,
+            };
+
+            if let Some(option) = rest.iter().find(|arg| arg.starts_with("--")) {
+                eprintln!("Error: Unknown option: {}", option);
+                return Ok(());
+            }
 
-            match input_file {
-                Some(file) => play_macro(file, loop_flag)?,
+            match rest.first() {
+                Some(file) => play_macro(file, options)?,
                 None => {
                     eprintln!("Error: No input file specified");
-                    eprintln!("Usage: evkey play [--loop] <input_file>");
+                    eprintln!("Usage: evkey play [options] <input_file>");
                     return Ok(());
                 }
             }
@@ -66,134 +393,349 @@ fn main() -> Result<(), Box<dyn Error>> {
 fn print_usage() {
     println!("EvKey - AutoHotkey-style macro recorder for Linux\n");
     println!("Usage:");
-    println!("  evkey record <output_file>       Record a macro to file");
-    println!("  evkey play [--loop] <input_file> Play back a recorded macro");
-    println!("  evkey list-devices               List available input devices");
+    println!("  evkey record [options] <output_file> Record a macro to file");
+    println!("  evkey play [options] <input_file>    Play back a recorded macro");
+    println!("  evkey list-devices                   List available input devices");
+    println!("\nRecord options:");
+    println!("  --hotkey KEYS          Start and stop hotkey, e.g. CTRL+ALT+R (default: F1)");
+    println!("  --start-hotkey KEYS    Start hotkey (default: F1)");
+    println!("  --stop-hotkey KEYS     Stop hotkey (default: F1)");
+    println!("  --pause-hotkey KEYS    Pause/resume hotkey (default: F2)");
+    println!("  --discard-hotkey KEYS  Discard and restart hotkey (default: F3)");
+    println!("  --device PATH          Record from this device, e.g. /dev/input/event3 or a");
+    println!("                         /dev/input/by-id link (repeatable)");
+    println!("  --device-name PATTERN  Record from devices whose name matches, e.g. \"Logitech*\"");
+    println!("  --device-id VID:PID    Record from devices with this hex vendor:product ID");
+    println!("  (without a device option, all keyboards, mice, tablets, touchscreens and");
+    println!("  touchpads are recorded)");
+    println!("  --gamepad              Record game controllers instead of keyboards and mice");
+    println!("  --grab                 Grab the devices so recorded input doesn't reach other");
+    println!("                         applications (released on stop, Ctrl-C or crash)");
+    println!("  --raw                  Save every event exactly (microsecond timing, scan codes,");
+    println!("                         key repeats) instead of readable hold/wait/move lines");
+    println!("  --mouse-threshold PX   Collect mouse movement into moves of at least this many");
+    println!("                         pixels (default: 5; nothing is lost, only combined)");
+    println!("  --simplify PX          Merge mouse moves while the path stays within PX pixels");
+    println!("                         of the recorded one (the end point is always exact)");
+    println!("  --quantize DURATION    Round timings in the saved macro to multiples of this");
+    println!("                         (default: 1ms; 1us keeps full precision)");
+    println!("\nPlay options:");
+    println!("  --delay DURATION       Countdown before playback starts (default: 3s)");
+    println!("  --start-on KEYS        Wait for this key on the real keyboard before starting");
+    println!("                         (no countdown unless --delay is given too)");
+    println!("  --loop                 Play the macro over and over");
+    println!("  --repeat N             Play the macro N times");
+    println!("  --interval DURATION    Wait this long between runs, e.g. 2s");
+    println!("  --until HH:MM          Don't start another run after this time of day");
+    println!("                         (repeats until then unless --repeat is given)");
+    println!("  --abort-hotkey KEYS    Stop playback and release all keys (default: F12;");
+    println!("                         Ctrl-C works too)");
+    println!("  --play-pause-hotkey KEYS");
+    println!("                         Pause/resume playback, releasing held keys (default: F11)");
+    println!("  --speed FACTOR         Playback speed, e.g. 2.0 for double speed or 0.5 for half");
+    println!("  --max-wait DURATION    Shorten idle waits to at most this long, e.g. 500ms");
+    println!("                         (key holds keep their length)");
+    println!("  --yield-to-user        Abort as soon as a real keyboard, mouse, touchpad,");
+    println!("                         touchscreen or tablet is used (or game controller,");
+    println!("                         if the macro plays one)");
+    println!("  --yield-pause          With --yield-to-user, pause instead of aborting");
+    println!("  --humanize             Randomize holds, waits and mouse movement on each run");
+    println!("  --humanize-time PCT    How much holds and waits vary (default: 10%)");
+    println!("  --humanize-mouse PX    How far the pointer strays from its path (default: 2)");
+    println!("  --seed N               Random seed, to repeat a humanized run exactly");
+    println!("  --key-repeat MODE      Held keys repeat as recorded, synthesize (like the");
+    println!("                         kernel) or off (default: recorded, so readable");
+    println!("                         macros don't repeat)");
+    println!("  --key-repeat-delay DURATION");
+    println!("                         Hold time before a key starts repeating (default: 250ms)");
+    println!("  --key-repeat-period DURATION");
+    println!("                         Time between repeats (default: 33ms)");
+    println!("\nDefaults can be set in ~/.config/evkey/config, e.g.:");
+    println!("  record_hotkey = CTRL+ALT+R");
+    println!("  pause_hotkey = CTRL+ALT+P");
+    println!("  abort_hotkey = CTRL+ALT+Q");
+    println!("  play_pause_hotkey = CTRL+ALT+SPACE");
+    println!("  simplify_mouse = 2");
     println!("\nNote: You may need to run with sudo to access input devices");
 }
 
+/// Remove `name VALUE` from the argument list and return VALUE
+///
+/// Fails if `name` is the last argument or followed by another option, so a
+/// forgotten value isn't silently ignored.
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    let Some(index) = args.iter().position(|arg| arg == name) else {
+        return Ok(None);
+    };
+    args.remove(index);
+    match args.get(index) {
+        Some(value) if !value.starts_with("--") => Ok(Some(args.remove(index))),
+        _ => Err(format!("{} needs a value", name)),
+    }
+}
+
+/// Remove every `name VALUE` from the argument list and return the VALUEs
+fn take_options(args: &mut Vec<String>, name: &str) -> Result<Vec<String>, String> {
+    let mut values = Vec::new();
+    while let Some(value) = take_option(args, name)? {
+        values.push(value);
+    }
+    Ok(values)
+}
+
+/// Remove `name` from the argument list, returning whether it was present
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let found = args.iter().any(|arg| arg == name);
+    args.retain(|arg| arg != name);
+    found
+}
+
+/// Settings for `evkey record`
+struct RecordOptions {
+    hotkeys: RecordHotkeys,
+    filter: DeviceFilter,
+    grab: bool,
+    /// Save every event exactly instead of the readable DSL
+    raw: bool,
+    mouse_filter: MouseFilter,
+    /// Granularity of the timings in the saved macro
+    quantize: Duration,
+}
+
+/// Settings for `evkey play`
+struct PlayOptions {
+    /// Countdown before the first run
+    delay: Duration,
+    /// Hotkey to wait for before the countdown, if any
+    start_on: Option<Hotkey>,
+    /// Overrides the macro file's repeat settings
+    repeat: Repeat,
+    timing: Timing,
+    /// Humanize settings and the seed they were created with
+    humanize: Option<(Humanize, u64)>,
+    hotkeys: PlayHotkeys,
+    /// Give way to real keyboard and mouse input, if set
+    yield_mode: Option<YieldMode>,
+    /// Where key repeats come from, if not the macro format's default
+    key_repeat: Option<KeyRepeat>,
+}
+
+/// Parse a percentage like "10%" (or "10") into a fraction
+fn parse_percent(s: &str) -> Option<f64> {
+    let percent: f64 = s.strip_suffix('%').unwrap_or(s).parse().ok()?;
+    (percent.is_finite() && percent >= 0.0).then_some(percent / 100.0)
+}
+
+/// Seed for when none was given, from the clock
+fn random_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
+
 fn list_devices() -> Result<(), Box<dyn Error>> {
     println!("Available input devices:\n");
 
-    for entry in std::fs::read_dir("/dev/input")? {
-        let entry = entry?;
-        let path = entry.path();
-
-        if let Some(name) = path.file_name() {
-            let name_str = name.to_string_lossy();
-            if name_str.starts_with("event") {
-                match evdev::Device::open(&path) {
-                    Ok(device) => {
-                        println!("  {} - {}",
-                            path.display(),
-                            device.name().unwrap_or("unknown")
-                        );
-                    }
-                    Err(_) => {
-                        // Skip devices we can't open
-                    }
+    for path in devices::event_devices()? {
+        match evdev::Device::open(&path) {
+            Ok(device) => {
+                let id = device.input_id();
+                println!("  {} - {} [{:04x}:{:04x}]",
+                    path.display(),
+                    device.name().unwrap_or("unknown"),
+                    id.vendor(),
+                    id.product()
+                );
+                for link in devices::stable_links(&path) {
+                    println!("      {}", link.display());
                 }
             }
+            Err(_) => {
+                // Skip devices we can't open
+            }
         }
     }
 
     Ok(())
 }
 
-fn record_macro(output_file: &str) -> Result<(), Box<dyn Error>> {
+fn record_macro(output_file: &str, options: RecordOptions) -> Result<(), Box<dyn Error>> {
+    let RecordOptions {
+        hotkeys,
+        filter,
+        grab,
+        raw,
+        mouse_filter,
+        quantize,
+    } = options;
+
     println!("EvKey Recorder");
     println!("==============\n");
 
-    println!("Auto-detecting keyboards and mice...\n");
+    if filter.is_empty() && filter.gamepad {
+        println!("Auto-detecting game controllers...\n");
+    } else if filter.is_empty() {
+        println!("Auto-detecting keyboards and mice...\n");
+    } else {
+        println!("Looking for selected devices...\n");
+    }
 
-    let mut recorder = Recorder::new();
+    let mut recorder = Recorder::new(hotkeys.clone(), filter.clone(), grab)?;
     let mut device_count = 0;
-
-    // Enumerate all devices and add keyboards/mice
-    for entry in std::fs::read_dir("/dev/input")? {
-        let entry = entry?;
-        let path = entry.path();
-
-        if let Some(name) = path.file_name() {
-            let name_str = name.to_string_lossy();
-            if name_str.starts_with("event") {
-                match evdev::Device::open(&path) {
-                    Ok(device) => {
-                        // Check if device has keys (keyboard) or relative axes (mouse)
-                        let has_keys = device.supported_keys().map_or(false, |keys| keys.iter().len() > 0);
-                        let has_relative = device.supported_relative_axes().map_or(false, |axes| axes.iter().len() > 0);
-
-                        if has_keys || has_relative {
-                            let device_type = match (has_keys, has_relative) {
-                                (true, true) => "keyboard+mouse",
-                                (true, false) => "keyboard",
-                                (false, true) => "mouse",
-                                _ => continue,
-                            };
-
-                            println!("  {} - {} ({})",
-                                path.display(),
-                                device.name().unwrap_or("unknown"),
-                                device_type
-                            );
-
-                            drop(device); // Close device before reopening in recorder
-                            match recorder.add_device(&path) {
-                                Ok(_) => device_count += 1,
-                                Err(e) => eprintln!("    Warning: Could not add device: {}", e),
-                            }
-                        }
-                    }
-                    Err(_) => {
-                        // Skip devices we can't open (permission issues, etc.)
+    let mut selected = Vec::new();
+    let mut keyboards = Vec::new();
+
+    // Enumerate all devices and pick the ones the filter selects
+    for path in devices::event_devices()? {
+        match evdev::Device::open(&path) {
+            Ok(device) => {
+                let kind = devices::device_kind(&device);
+                if !filter.matches(&path, &device) {
+                    // Hotkeys still have to reach the recorder
+                    if matches!(kind, Some(DeviceKind::Keyboard | DeviceKind::KeyboardMouse)) {
+                        keyboards.push(path);
                     }
+                    continue;
                 }
+                let name = device.name().unwrap_or("unknown").to_string();
+                selected.push((path, name, kind));
             }
+            Err(_) => {
+                // Skip devices we can't open (permission issues, etc.)
+            }
+        }
+    }
+
+    // Recorded events don't say which device they came from, so playback can
+    // only recreate one absolute-axis device
+    let absolute: Vec<_> = selected
+        .iter()
+        .filter(|(_, _, kind)| kind.is_some_and(DeviceKind::is_absolute))
+        .collect();
+    if absolute.len() > 1 {
+        eprintln!("Error: Only one tablet, touchscreen, touchpad or game controller can be");
+        eprintln!("recorded at a time, but these were selected:");
+        for (path, name, kind) in absolute {
+            let kind = kind.map_or("other", DeviceKind::name);
+            eprintln!("  {} - {} ({})", path.display(), name, kind);
+        }
+        eprintln!("Pick one with --device, adding --device for each keyboard and mouse to record.");
+        return Ok(());
+    }
+
+    for (path, name, kind) in selected {
+        println!("  {} - {} ({})",
+            path.display(),
+            name,
+            kind.map_or("other", DeviceKind::name)
+        );
+
+        match recorder.add_device(&path) {
+            Ok(_) => device_count += 1,
+            Err(e) => eprintln!("    Warning: Could not add device: {}", e),
         }
     }
 
     if device_count == 0 {
-        eprintln!("\nError: No keyboard or mouse devices found!");
+        if filter.is_empty() && filter.gamepad {
+            eprintln!("\nError: No game controllers found!");
+        } else if filter.is_empty() {
+            eprintln!("\nError: No keyboard or mouse devices found!");
+        } else {
+            eprintln!("\nError: No devices matched the --device options!");
+        }
         eprintln!("Make sure you're running with sudo or have appropriate permissions.");
         return Ok(());
     }
 
     println!("\nFound {} input device(s)", device_count);
 
+    for path in keyboards {
+        if let Err(e) = recorder.add_control_device(&path) {
+            eprintln!("Warning: Could not watch {} for hotkeys: {}", path.display(), e);
+        }
+    }
+
     println!("\n=== HOTKEY CONTROLS ===");
-    println!("Press F1 to START recording");
-    println!("Press F1 again to STOP recording");
+    println!("Press {} to START recording", hotkeys.start);
+    println!("Press {} to STOP recording", hotkeys.stop);
+    println!("Press {} to PAUSE/RESUME recording", hotkeys.pause);
+    println!("Press {} to DISCARD and restart recording", hotkeys.discard);
     println!("========================\n");
-    println!("Waiting for F1 to start...");
+    println!("Waiting for {} to start...", hotkeys.start);
 
-    // Poll for events until recording starts and stops
-    loop {
+    // Wait for events until recording starts and stops
+    'recording: loop {
         match recorder.poll() {
-            Ok(state_changed) => {
-                if state_changed {
-                    if recorder.is_recording() {
-                        println!("\n>>> Recording started! Perform your macro actions...");
-                    } else {
-                        println!(">>> Recording stopped!");
-                        break;
+            Ok(transitions) => {
+                for transition in transitions {
+                    match transition {
+                        Transition::Started => {
+                            println!("\n>>> Recording started! Perform your macro actions...");
+                        }
+                        Transition::Paused => {
+                            println!(
+                                ">>> Recording paused ({} events so far)",
+                                recorder.events().len()
+                            );
+                        }
+                        Transition::Resumed => println!(">>> Recording resumed"),
+                        Transition::Discarded => {
+                            println!(">>> Recording discarded, starting over...");
+                        }
+                        Transition::Stopped => {
+                            println!(">>> Recording stopped!");
+                            break 'recording;
+                        }
+                        Transition::Interrupted => {
+                            println!("\n>>> Interrupted, nothing saved");
+                            return Ok(());
+                        }
                     }
                 }
             },
             Err(e) => eprintln!("Error polling: {}", e),
         }
-        thread::sleep(Duration::from_millis(1));
     }
 
     let events = recorder.stop();
 
-    println!("\nSaving {} events to {}...", events.len(), output_file);
-    storage::save(output_file, &events)?;
+    // Only describe the absolute device if it was actually used
+    let abs_device = recorder.abs_device().filter(|abs_device| {
+        events.iter().any(|e| match e.event.event_type() {
+            evdev::EventType::ABSOLUTE => true,
+            evdev::EventType::KEY => abs_device.buttons.contains(&e.event.code()),
+            _ => false,
+        })
+    });
+    let recording = storage::Macro {
+        abs_device: abs_device.cloned(),
+        events,
+        repeat: Repeat::default(),
+        format: if raw { Format::Raw } else { Format::Dsl },
+        mouse_filter,
+        quantize,
+    };
+
+    println!("\nSaving {} events to {}...", recording.events.len(), output_file);
+    storage::save(output_file, &recording)?;
     println!("Macro saved successfully!");
 
     Ok(())
 }
 
-fn play_macro(input_file: &str, loop_forever: bool) -> Result<(), Box<dyn Error>> {
+fn play_macro(input_file: &str, options: PlayOptions) -> Result<(), Box<dyn Error>> {
+    let PlayOptions {
+        delay,
+        start_on,
+        repeat,
+        timing,
+        humanize,
+        hotkeys,
+        yield_mode,
+        key_repeat,
+    } = options;
+
     println!("EvKey Player");
     println!("============\n");
 
@@ -203,24 +745,141 @@ fn play_macro(input_file: &str, loop_forever: bool) -> Result<(), Box<dyn Error>
     }
 
     println!("Loading macro from {}...", input_file);
-    let events = storage::load(input_file)?;
+    let recording = storage::load(input_file)?;
+    let (events, skipped) =
+        player::playable_events(&recording.events, recording.abs_device.as_ref());
+    let events = &events;
+    let repeat = recording.repeat.overridden_by(repeat);
+    let deadline = repeat.until.map(repeat::next_time_of_day).transpose()?;
 
     println!("Loaded {} events", events.len());
-    println!("\nStarting playback in 3 seconds...");
+    if skipped > 0 {
+        println!(
+            "Skipping {} events the virtual devices can't play (e.g. MSC_TIMESTAMP, LEDs)",
+            skipped
+        );
+    }
 
-    thread::sleep(Duration::from_secs(3));
+    // Opened before the player, whose virtual devices are skipped anyway
+    let plays_controller = recording
+        .abs_device
+        .as_ref()
+        .and_then(|abs_device| abs_device.kind)
+        .is_some_and(DeviceKind::is_controller);
+    let monitor =
+        InputMonitor::new(hotkeys.clone(), PLAYBACK_DEVICE, yield_mode, plays_controller)?;
+    if monitor.device_count() == 0 {
+        if let Some(start_on) = &start_on {
+            // Nothing could ever press the start hotkey
+            return Err(format!(
+                "No keyboards to watch for {} (try running with sudo)",
+                start_on
+            )
+            .into());
+        }
+        eprintln!("Warning: No keyboards to watch, only Ctrl-C can stop playback");
+    }
 
-    let mut player = Player::new("evkey-playback")?;
+    // Raw macros have the repeats that were recorded, readable ones have none
+    let key_repeat = key_repeat.unwrap_or(KeyRepeat::Recorded);
+    let mut player = Player::new(PLAYBACK_DEVICE, recording.abs_device.as_ref(), key_repeat)?;
+    player.set_monitor(Some(monitor));
+    player.set_timing(timing);
+    if let Some((humanize, seed)) = humanize {
+        println!("Humanizing playback (seed {}, pass --seed {} to repeat)", seed, seed);
+        player.set_humanize(Some(humanize));
+    }
 
-    loop {
-        player.play(&events)?;
+    println!("Press {} to abort playback", hotkeys.abort);
+    println!("Press {} to pause/resume playback", hotkeys.pause);
+    match yield_mode {
+        Some(YieldMode::Abort) => println!("Using a real input device aborts playback"),
+        Some(YieldMode::Pause) => println!("Using a real input device pauses playback"),
+        None => {}
+    }
 
-        if loop_forever {
-            println!("\nFinished macro, starting again...");
-        } else {
+    if let Some(start_on) = &start_on {
+        println!("\nPress {} to start playback...", start_on);
+        if let Some(control) = player.wait_for_hotkey(start_on)? {
+            print_stopped(control);
+            return Ok(());
+        }
+    }
+    if let Some(control) = countdown(&mut player, delay)? {
+        print_stopped(control);
+        return Ok(());
+    }
+
+    let limit = repeat.limit();
+    if let Some((hour, minute)) = repeat.until {
+        println!("Repeating until {:02}:{:02}", hour, minute);
+    }
+
+    for run in 1.. {
+        match limit {
+            Some(1) => {}
+            Some(runs) => println!("\n>>> Run {} of {}", run, runs),
+            None => println!("\n>>> Run {}", run),
+        }
+
+        if let Outcome::Stopped(control) = player.play(events)? {
+            print_stopped(control);
             break;
         }
+
+        if limit.is_some_and(|runs| run >= runs) {
+            break;
+        }
+
+        if let Some(interval) = repeat.interval {
+            println!("Next run in {:?}...", interval);
+            if let Some(control) = player.wait(interval)? {
+                print_stopped(control);
+                break;
+            }
+        }
+
+        if let (Some(deadline), Some((hour, minute))) = (deadline, repeat.until) {
+            if SystemTime::now() >= deadline {
+                println!("\n>>> It's past {:02}:{:02}, stopping", hour, minute);
+                break;
+            }
+        }
     }
 
     Ok(())
 }
+
+/// Count down the seconds until playback starts
+///
+/// Returns the control that cancelled playback during the countdown, if any.
+fn countdown(player: &mut Player, delay: Duration) -> io::Result<Option<Control>> {
+    let mut remaining = delay;
+
+    while !remaining.is_zero() {
+        let seconds = remaining.as_secs_f64().ceil() as u64;
+        print!("\rStarting playback in {}... ", seconds);
+        io::stdout().flush()?;
+
+        // Wait until the next whole second remains
+        let step = remaining - Duration::from_secs(seconds - 1);
+        if let Some(control) = player.wait(step)? {
+            println!();
+            return Ok(Some(control));
+        }
+        remaining -= step;
+    }
+
+    if !delay.is_zero() {
+        println!("\rStarting playback now!    ");
+    }
+    Ok(None)
+}
+
+/// Tell the user why playback stopped early
+fn print_stopped(control: Control) {
+    match control {
+        Control::Interrupt => println!("\n>>> Interrupted"),
+        _ => println!("\n>>> Playback aborted"),
+    }
+}
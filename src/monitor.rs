@@ -0,0 +1,289 @@
+//! Watching the real input devices while a macro plays
+//!
+//! Playback waits on the keyboards (and SIGINT/SIGTERM) instead of plain
+//! sleeping, so the user can always stop or pause a macro, even a looping one.
+//! In yield-to-user mode any real input stops or pauses it.
+
+use crate::devices::{self, DeviceKind};
+use crate::hotkey::Hotkey;
+use crate::signals::TerminationSignals;
+use evdev::{Device, EventSummary, KeyCode};
+use nix::errno::Errno;
+use nix::sys::epoll::{Epoll, EpollCreateFlags, EpollEvent, EpollFlags, EpollTimeout};
+use std::collections::HashSet;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Hotkeys that control playback
+#[derive(Debug, Clone)]
+pub struct PlayHotkeys {
+    /// Stop playback, releasing any keys the macro holds
+    pub abort: Hotkey,
+    /// Pause or resume playback, releasing held keys while paused
+    pub pause: Hotkey,
+}
+
+impl Default for PlayHotkeys {
+    fn default() -> Self {
+        Self {
+            abort: Hotkey::parse("F12").expect("valid default hotkey"),
+            pause: Hotkey::parse("F11").expect("valid default hotkey"),
+        }
+    }
+}
+
+/// Something the user did that playback has to react to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    /// The abort hotkey was pressed
+    Abort,
+    /// The pause hotkey was pressed, to pause or to resume
+    Pause,
+    /// SIGINT or SIGTERM received
+    Interrupt,
+    /// The hotkey given to `wait_for_start` was pressed
+    Start,
+}
+
+/// What to do when the user touches the keyboard or mouse during playback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YieldMode {
+    Abort,
+    /// Pause until the pause hotkey is pressed
+    Pause,
+}
+
+/// Epoll data for SIGINT/SIGTERM (device fds use their fd)
+const SIGNAL_TOKEN: u64 = u64::MAX;
+
+pub struct InputMonitor {
+    devices: Vec<Device>,
+    /// Wakes us when a keyboard has input or a signal arrives
+    epoll: Epoll,
+    /// Ctrl-C and SIGTERM, so held keys are released before exiting
+    signals: TerminationSignals,
+    hotkeys: PlayHotkeys,
+    /// Keys currently held down on the real keyboards
+    held_keys: HashSet<u16>,
+    /// Give way to real input, if enabled
+    yield_mode: Option<YieldMode>,
+    /// Hotkey that starts playback, while waiting for it
+    start: Option<Hotkey>,
+}
+
+impl InputMonitor {
+    /// Watch every keyboard, and in yield-to-user mode every mouse, tablet,
+    /// touchscreen and touchpad too, plus game controllers if `controllers`
+    /// (when the macro plays one)
+    ///
+    /// Devices whose name starts with `ignore_prefix` are our own virtual
+    /// playback devices; watching them would make the macro's own input look
+    /// like the user's, and playback would cancel itself.
+    pub fn new(
+        hotkeys: PlayHotkeys,
+        ignore_prefix: &str,
+        yield_mode: Option<YieldMode>,
+        controllers: bool,
+    ) -> io::Result<Self> {
+        let epoll = Epoll::new(EpollCreateFlags::EPOLL_CLOEXEC)?;
+        let signals = TerminationSignals::new()?;
+        epoll.add(&signals, EpollEvent::new(EpollFlags::EPOLLIN, SIGNAL_TOKEN))?;
+
+        let mut devices = Vec::new();
+        for path in devices::event_devices()? {
+            let Ok(device) = Device::open(&path) else {
+                continue;
+            };
+            if device.name().unwrap_or("").starts_with(ignore_prefix) {
+                continue;
+            }
+            let watched = match devices::device_kind(&device) {
+                Some(DeviceKind::Keyboard | DeviceKind::KeyboardMouse) => true,
+                Some(
+                    DeviceKind::Mouse
+                    | DeviceKind::Tablet
+                    | DeviceKind::Touchscreen
+                    | DeviceKind::Touchpad,
+                ) => yield_mode.is_some(),
+                Some(kind) if kind.is_controller() => yield_mode.is_some() && controllers,
+                _ => false,
+            };
+            if !watched {
+                continue;
+            }
+
+            device.set_nonblocking(true)?;
+            let fd = device.as_raw_fd();
+            epoll.add(&device, EpollEvent::new(EpollFlags::EPOLLIN, fd as u64))?;
+            devices.push(device);
+        }
+
+        Ok(Self {
+            devices,
+            epoll,
+            signals,
+            hotkeys,
+            held_keys: HashSet::new(),
+            yield_mode,
+            start: None,
+        })
+    }
+
+    /// Number of devices being watched
+    pub fn device_count(&self) -> usize {
+        self.devices.len()
+    }
+
+    /// Wait until `deadline`, returning early if the user wants playback to
+    /// stop or pause
+    ///
+    /// Input is checked even if the deadline has already passed, so a macro
+    /// running behind schedule can still be stopped. With `yielding`, real
+    /// input triggers the yield mode; that's only wanted while the macro runs.
+    pub fn wait_until(&mut self, deadline: Instant, yielding: bool) -> io::Result<Option<Control>> {
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            // epoll only has millisecond resolution, so sleep off the rest
+            if remaining < Duration::from_millis(1) {
+                let control = self.poll(EpollTimeout::ZERO, yielding)?;
+                if control.is_none() && !remaining.is_zero() {
+                    thread::sleep(remaining);
+                }
+                return Ok(control);
+            }
+
+            let timeout = EpollTimeout::try_from(remaining).unwrap_or(EpollTimeout::MAX);
+            if let Some(control) = self.poll(timeout, yielding)? {
+                return Ok(Some(control));
+            }
+        }
+    }
+
+    /// Wait as long as it takes for the user to press a hotkey (or for a
+    /// signal), e.g. while paused
+    ///
+    /// Other input is the user's business here and never yields.
+    pub fn wait(&mut self) -> io::Result<Control> {
+        loop {
+            if let Some(control) = self.poll(EpollTimeout::NONE, false)? {
+                return Ok(control);
+            }
+        }
+    }
+
+    /// Wait for `hotkey` to be pressed before playback starts
+    ///
+    /// Returns the control that cancelled playback instead, if any.
+    pub fn wait_for_start(&mut self, hotkey: Hotkey) -> io::Result<Option<Control>> {
+        self.start = Some(hotkey);
+        let result = loop {
+            match self.wait() {
+                Ok(Control::Start) => break Ok(None),
+                // Nothing is playing yet, so there's nothing to pause
+                Ok(Control::Pause) => continue,
+                Ok(control) => break Ok(Some(control)),
+                Err(e) => break Err(e),
+            }
+        };
+        self.start = None;
+        result
+    }
+
+    /// Wait up to `timeout` for input, and handle whatever arrived
+    ///
+    /// With `yielding`, real input other than hotkeys triggers the yield mode.
+    fn poll(&mut self, timeout: EpollTimeout, yielding: bool) -> io::Result<Option<Control>> {
+        let mut ready = [EpollEvent::empty(); 16];
+        let count = match self.epoll.wait(&mut ready, timeout) {
+            Ok(count) => count,
+            Err(Errno::EINTR) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let ready_fds: Vec<u64> = ready[..count].iter().map(|e| e.data()).collect();
+
+        if ready_fds.contains(&SIGNAL_TOKEN) && self.signals.received() {
+            return Ok(Some(Control::Interrupt));
+        }
+
+        let mut fetched = Vec::new();
+        let mut unplugged = Vec::new();
+
+        for (index, device) in self.devices.iter_mut().enumerate() {
+            if !ready_fds.contains(&(device.as_raw_fd() as u64)) {
+                continue;
+            }
+
+            match device.fetch_events() {
+                Ok(events) => fetched.extend(events),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(ref e) if e.raw_os_error() == Some(Errno::ENODEV as i32) => {
+                    unplugged.push(index);
+                }
+                Err(e) => eprintln!("Device read error: {}", e),
+            }
+        }
+
+        for index in unplugged.into_iter().rev() {
+            // The fd may already be gone along with the device, so ignore errors
+            let removed = self.devices.remove(index);
+            let _ = self.epoll.delete(&removed);
+        }
+
+        let mut control = None;
+        let mut user_input = false;
+        for event in fetched {
+            match event.destructure() {
+                EventSummary::Key(_, KeyCode(code), value) => match value {
+                    1 => {
+                        self.held_keys.insert(code);
+                        if self.hotkeys.abort.is_triggered(code, &self.held_keys) {
+                            control = Some(Control::Abort);
+                        } else if self.hotkeys.pause.is_triggered(code, &self.held_keys) {
+                            control = control.or(Some(Control::Pause));
+                        } else if self
+                            .start
+                            .as_ref()
+                            .is_some_and(|start| start.is_triggered(code, &self.held_keys))
+                        {
+                            control = control.or(Some(Control::Start));
+                        } else if !self.is_hotkey_key(code) {
+                            user_input = true;
+                        }
+                    }
+                    0 => {
+                        // Releases don't count as input, or letting go of the
+                        // pause hotkey after resuming would pause again
+                        self.held_keys.remove(&code);
+                    }
+                    _ => {}
+                },
+                // Pointer motion, touches, pen strokes and sticks
+                EventSummary::RelativeAxis(..) | EventSummary::AbsoluteAxis(..) => {
+                    user_input = true;
+                }
+                _ => {}
+            }
+        }
+
+        if let (None, true, true, Some(mode)) = (control, user_input, yielding, self.yield_mode) {
+            println!("\n>>> Real input detected");
+            control = Some(match mode {
+                YieldMode::Abort => Control::Abort,
+                YieldMode::Pause => Control::Pause,
+            });
+        }
+
+        Ok(control)
+    }
+
+    /// Check if `code` is part of a hotkey chord
+    ///
+    /// Pressing the first keys of a chord mustn't yield, or the pause hotkey
+    /// would pause and then immediately resume.
+    fn is_hotkey_key(&self, code: u16) -> bool {
+        self.hotkeys.abort.keys().contains(&code) || self.hotkeys.pause.keys().contains(&code)
+    }
+}
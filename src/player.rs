@@ -1,18 +1,137 @@
 //! Playing back recorded events
 
+use crate::autorepeat::KeyRepeat;
+use crate::hotkey::Hotkey;
+use crate::humanize::Humanize;
+use crate::keymap;
+use crate::monitor::{Control, InputMonitor};
+use crate::profile::AbsDevice;
 use crate::recorder::RecordedEvent;
-use evdev::{uinput::VirtualDevice, AttributeSet, KeyCode, RelativeAxisCode};
+use evdev::{
+    uinput::VirtualDevice, AbsoluteAxisCode, AttributeSet, BusType, EventType, InputEvent,
+    InputId, KeyCode, MiscCode, PropType, RelativeAxisCode, SynchronizationCode, UinputAbsSetup,
+};
+use std::collections::HashSet;
+use std::fmt;
 use std::io;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+/// How late frames were emitted compared to their scheduled time
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PlaybackStats {
+    /// Number of frames emitted
+    pub frames: u32,
+    /// Largest lateness of any frame
+    pub max_lateness: Duration,
+    /// Sum of all lateness, for the mean
+    total_lateness: Duration,
+}
+
+impl PlaybackStats {
+    /// Record how late a frame was
+    fn record(&mut self, lateness: Duration) {
+        self.frames += 1;
+        self.max_lateness = self.max_lateness.max(lateness);
+        self.total_lateness += lateness;
+    }
+
+    /// Average lateness over all frames
+    pub fn mean_lateness(&self) -> Duration {
+        if self.frames == 0 {
+            Duration::ZERO
+        } else {
+            self.total_lateness / self.frames
+        }
+    }
+}
+
+impl fmt::Display for PlaybackStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} frames, lateness max {:.3}ms, mean {:.3}ms",
+            self.frames,
+            self.max_lateness.as_secs_f64() * 1000.0,
+            self.mean_lateness().as_secs_f64() * 1000.0
+        )
+    }
+}
+
+/// How a run of `Player::play` ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    /// Every frame was played
+    Finished(PlaybackStats),
+    /// The user stopped playback; held keys have been released
+    Stopped(Control),
+}
+
+/// Slowest playback speed; slower would stretch a held key's synthesized
+/// repeats into millions of events
+pub const MIN_SPEED: f64 = 0.01;
+
+/// How the recorded timing is changed on playback
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timing {
+    /// Playback speed multiplier, 2.0 plays twice as fast
+    pub speed: f64,
+    /// Longest idle gap (no keys held) between frames, after applying speed
+    pub max_wait: Option<Duration>,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            max_wait: None,
+        }
+    }
+}
+
+/// Relative axes of the virtual mouse; raw macros replay the high-resolution
+/// wheel events alongside the low-resolution ones
+const RELATIVE_AXES: [RelativeAxisCode; 6] = [
+    RelativeAxisCode::REL_X,
+    RelativeAxisCode::REL_Y,
+    RelativeAxisCode::REL_WHEEL,
+    RelativeAxisCode::REL_HWHEEL,
+    RelativeAxisCode::REL_WHEEL_HI_RES,
+    RelativeAxisCode::REL_HWHEEL_HI_RES,
+];
+
+/// Misc events of the virtual keyboard: scan codes, which raw macros keep
+/// next to key events
+const MISC_CODES: [MiscCode; 1] = [MiscCode::MSC_SCAN];
 
 pub struct Player {
     device: VirtualDevice,
+    /// Recreation of the tablet/touchscreen/touchpad/controller a macro was
+    /// recorded from, with the buttons that belong to it
+    abs: Option<(VirtualDevice, Vec<u16>)>,
+    timing: Timing,
+    /// Where key repeats come from
+    key_repeat: KeyRepeat,
+    /// Random variation for each run, if enabled
+    humanize: Option<Humanize>,
+    /// Real keyboards watched for the abort and pause hotkeys, if any
+    monitor: Option<InputMonitor>,
+    /// Keys and buttons the virtual devices currently hold down, in the
+    /// order they were pressed
+    held_keys: Vec<u16>,
 }
 
 impl Player {
-    /// Create a new player with a virtual device
-    pub fn new(device_name: &str) -> io::Result<Self> {
+    /// Create a new player with a virtual keyboard+mouse, plus a virtual
+    /// absolute-axis device if the macro needs one
+    ///
+    /// `key_repeat` decides whether held keys repeat as recorded, repeat with
+    /// a given delay and period, or don't repeat at all.
+    pub fn new(
+        device_name: &str,
+        abs_device: Option<&AbsDevice>,
+        key_repeat: KeyRepeat,
+    ) -> io::Result<Self> {
         // Setup all keyboard keys
         let mut keys = AttributeSet::<KeyCode>::new();
         // KEY_MAX is 0x2ff (767) - we register all possible keycodes
@@ -22,59 +141,272 @@ impl Player {
 
         // Setup mouse relative axes
         let mut relative_axes = AttributeSet::<RelativeAxisCode>::new();
-        relative_axes.insert(RelativeAxisCode::REL_X);
-        relative_axes.insert(RelativeAxisCode::REL_Y);
-        relative_axes.insert(RelativeAxisCode::REL_WHEEL);
-        relative_axes.insert(RelativeAxisCode::REL_HWHEEL);
+        for axis in RELATIVE_AXES {
+            relative_axes.insert(axis);
+        }
+
+        let mut misc = AttributeSet::<MiscCode>::new();
+        for code in MISC_CODES {
+            misc.insert(code);
+        }
 
         let device = VirtualDevice::builder()?
             .name(device_name)
             .with_keys(&keys)?
             .with_relative_axes(&relative_axes)?
+            .with_msc(&misc)?
             .build()?;
 
-        Ok(Self { device })
+        let abs = match abs_device {
+            Some(abs_device) => {
+                let kind = abs_device.kind.map_or("absolute", |kind| kind.name());
+                let name = format!("{} ({})", device_name, kind);
+                Some((build_abs_device(&name, abs_device)?, abs_device.buttons.clone()))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            device,
+            abs,
+            timing: Timing::default(),
+            key_repeat,
+            humanize: None,
+            monitor: None,
+            held_keys: Vec::new(),
+        })
+    }
+
+    /// Change the speed and idle gaps of playback
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+    }
+
+    /// Randomize holds, waits and mouse movement on every run
+    pub fn set_humanize(&mut self, humanize: Option<Humanize>) {
+        self.humanize = humanize;
+    }
+
+    /// Watch the real input devices while playing, so playback can be stopped
+    /// and paused
+    pub fn set_monitor(&mut self, monitor: Option<InputMonitor>) {
+        self.monitor = monitor;
+    }
+
+    /// Send a frame of events to the virtual devices they belong to
+    ///
+    /// Each device gets its share of the frame in a single write, terminated
+    /// by one SYN_REPORT, so applications see it as one update.
+    fn emit_frame(&mut self, frame: &[RecordedEvent]) -> io::Result<()> {
+        let mut main_events = Vec::with_capacity(frame.len());
+        let mut abs_events = Vec::new();
+
+        for recorded in frame {
+            let event = recorded.event;
+            if event.event_type() == EventType::KEY {
+                let code = event.code();
+                match event.value() {
+                    0 => self.held_keys.retain(|&held| held != code),
+                    1 if !self.held_keys.contains(&code) => self.held_keys.push(code),
+                    _ => {}
+                }
+            }
+            let belongs_to_abs = self
+                .abs
+                .as_ref()
+                .is_some_and(|(_, buttons)| routes_to_abs(&event, buttons));
+
+            if belongs_to_abs {
+                abs_events.push(event);
+            } else {
+                main_events.push(event);
+            }
+        }
+
+        if !main_events.is_empty() {
+            self.device.emit(&main_events)?;
+        }
+        if let (Some((abs_device, _)), false) = (&mut self.abs, abs_events.is_empty()) {
+            abs_device.emit(&abs_events)?;
+        }
+        Ok(())
+    }
+
+    /// Release every key the virtual devices hold, last pressed first
+    fn release_held_keys(&mut self) -> io::Result<()> {
+        while let Some(&code) = self.held_keys.last() {
+            let release = RecordedEvent {
+                timestamp_us: 0,
+                event: InputEvent::new(EventType::KEY.0, code, 0),
+            };
+            // Removes the key from held_keys
+            self.emit_frame(&[release])?;
+        }
+        Ok(())
+    }
+
+    /// Press keys again after a pause, in their original order
+    fn press_keys(&mut self, keys: &[u16]) -> io::Result<()> {
+        for &code in keys {
+            let press = RecordedEvent {
+                timestamp_us: 0,
+                event: InputEvent::new(EventType::KEY.0, code, 1),
+            };
+            self.emit_frame(&[press])?;
+        }
+        Ok(())
+    }
+
+    /// Wait until `deadline`, watching for the user's hotkeys (and, if
+    /// `yielding`, any real input) if there is a monitor
+    fn wait_until(&mut self, deadline: Instant, yielding: bool) -> io::Result<Option<Control>> {
+        match &mut self.monitor {
+            Some(monitor) => monitor.wait_until(deadline, yielding),
+            None => {
+                let now = Instant::now();
+                if deadline > now {
+                    thread::sleep(deadline - now);
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Pause until the pause hotkey is pressed again, with held keys released
+    ///
+    /// Returns how long playback was paused, or the control that stopped
+    /// playback instead of resuming it.
+    fn pause(&mut self) -> io::Result<Result<Duration, Control>> {
+        let paused_at = Instant::now();
+        let held_keys = self.held_keys.clone();
+        self.release_held_keys()?;
+        println!(">>> Playback paused");
+
+        let Some(monitor) = &mut self.monitor else {
+            return Ok(Ok(Duration::ZERO));
+        };
+        let control = monitor.wait()?;
+        if control != Control::Pause {
+            return Ok(Err(control));
+        }
+
+        // Put the macro back in the state it was paused in
+        self.press_keys(&held_keys)?;
+        println!(">>> Playback resumed");
+        Ok(Ok(paused_at.elapsed()))
+    }
+
+    /// Wait until `deadline`, moved back by any time spent paused on the way
+    ///
+    /// Returns the total time paused, or the control that stopped playback.
+    fn wait_pausable(
+        &mut self,
+        mut deadline: Instant,
+        yielding: bool,
+    ) -> io::Result<Result<Duration, Control>> {
+        let mut paused_total = Duration::ZERO;
+        loop {
+            match self.wait_until(deadline, yielding)? {
+                Some(Control::Pause) => match self.pause()? {
+                    Ok(paused) => {
+                        paused_total += paused;
+                        deadline += paused;
+                    }
+                    Err(control) => return Ok(Err(control)),
+                },
+                Some(control) => return Ok(Err(control)),
+                None => return Ok(Ok(paused_total)),
+            }
+        }
+    }
+
+    /// Wait for the user to press `hotkey` on a real keyboard
+    ///
+    /// Returns the control that cancelled playback instead, if any. Without a
+    /// monitor there's no keyboard to wait on, so this returns straight away.
+    pub fn wait_for_hotkey(&mut self, hotkey: &Hotkey) -> io::Result<Option<Control>> {
+        match &mut self.monitor {
+            Some(monitor) => monitor.wait_for_start(hotkey.clone()),
+            None => Ok(None),
+        }
+    }
+
+    /// Wait before or between runs, still responding to the abort and pause
+    /// hotkeys
+    ///
+    /// The macro isn't running, so real input doesn't yield: the user is
+    /// free to focus a window during the countdown, for example. Returns the
+    /// control that stopped playback, if any.
+    pub fn wait(&mut self, duration: Duration) -> io::Result<Option<Control>> {
+        Ok(self.wait_pausable(Instant::now() + duration, false)?.err())
     }
 
     /// Play back recorded events with original timing
     ///
     /// # Current Implementation Notes:
-    /// - Events are played back sequentially with sleep delays between them
-    /// - Simultaneous events (same timestamp) are emitted separately with microsecond-level
-    ///   delays between them, rather than being batched into a single emit call
-    /// - This is functionally equivalent for most use cases, but true simultaneous events
-    ///   could be batched together for more accurate playback
+    /// - Events are grouped into frames delimited by SYN_REPORT, and each frame
+    ///   is emitted in a single call at the timestamp of its first event, so a
+    ///   diagonal mouse move or a chord arrives as one update
+    /// - Each frame is scheduled against an absolute deadline (playback start plus
+    ///   its timestamp), so oversleeping doesn't accumulate: a late frame makes the
+    ///   following ones wait less, instead of shifting everything after it
     /// - Held keys with different durations work correctly because press/release are
-    ///   separate events with their own timestamps
-    pub fn play(&mut self, events: &[RecordedEvent]) -> io::Result<()> {
+    ///   separate frames with their own timestamps
+    /// - In humanize mode the events are randomized first, differently on each run
+    /// - The timestamps are then adjusted by the player's `Timing`
+    /// - Key repeats are then kept, generated or left out, as set in `new`;
+    ///   generated ones follow the adjusted timeline, so `--speed` doesn't
+    ///   change how fast a held key repeats
+    /// - With a monitor, waiting between frames watches for the abort hotkey and
+    ///   SIGINT/SIGTERM; either stops playback and releases all held keys
+    /// - The pause hotkey releases held keys until it's pressed again, then
+    ///   presses them again and shifts the rest of the schedule by the pause
+    ///
+    /// Returns how late the frames were emitted, which is also printed.
+    pub fn play(&mut self, events: &[RecordedEvent]) -> io::Result<Outcome> {
+        let mut stats = PlaybackStats::default();
+
         if events.is_empty() {
             println!("No events to play");
-            return Ok(());
+            return Ok(Outcome::Finished(stats));
         }
 
         println!("Playing {} events...", events.len());
 
-        let mut last_timestamp = 0u64;
-
-        for recorded in events {
-            // Calculate delay from last event
-            let delay_us = recorded.timestamp_us.saturating_sub(last_timestamp);
-            if delay_us > 0 {
-                thread::sleep(Duration::from_micros(delay_us));
+        let humanized;
+        let events = match &mut self.humanize {
+            Some(humanize) => {
+                humanized = humanize.apply(events);
+                &humanized
             }
+            None => events,
+        };
 
-            // TODO: For better accuracy, could batch events with identical timestamps
-            // and emit them together in a single call
-            self.device.emit(&[recorded.event])?;
+        let events = timed_events(events, &self.timing, self.key_repeat);
+        let mut start = Instant::now();
 
-            last_timestamp = recorded.timestamp_us;
+        for frame in frames(&events) {
+            let timestamp_us = frame[0].timestamp_us;
+            match self.wait_pausable(deadline_at(start, timestamp_us), true)? {
+                Ok(paused) => start += paused,
+                Err(control) => {
+                    self.release_held_keys()?;
+                    println!("Playback stopped ({})", stats);
+                    return Ok(Outcome::Stopped(control));
+                }
+            }
+
+            let deadline = deadline_at(start, timestamp_us);
+            stats.record(Instant::now().saturating_duration_since(deadline));
+            self.emit_frame(frame)?;
         }
 
-        println!("Playback complete");
-        Ok(())
+        println!("Playback complete ({})", stats);
+        Ok(Outcome::Finished(stats))
     }
 
     /// Play back events instantly without timing delays
+    #[allow(dead_code)]
     pub fn play_instant(&mut self, events: &[RecordedEvent]) -> io::Result<()> {
         if events.is_empty() {
             println!("No events to play");
@@ -83,11 +415,373 @@ impl Player {
 
         println!("Playing {} events (instant mode)...", events.len());
 
-        for recorded in events {
-            self.device.emit(&[recorded.event])?;
+        for frame in frames(events) {
+            self.emit_frame(frame)?;
         }
 
         println!("Playback complete");
         Ok(())
     }
 }
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        // Don't leave keys stuck down if playback ends with an error or panic
+        let _ = self.release_held_keys();
+    }
+}
+
+/// Check if an event goes to the absolute-axis device, which reports
+/// `buttons`, rather than the keyboard+mouse
+///
+/// Mouse buttons always go to the mouse, even if an older macro lists them
+/// among a touchpad's buttons. SYN_MT_REPORT separates the contacts of a
+/// type A multitouch frame, so it goes with their axes.
+fn routes_to_abs(event: &InputEvent, buttons: &[u16]) -> bool {
+    match event.event_type() {
+        EventType::ABSOLUTE => true,
+        EventType::SYNCHRONIZATION => event.code() == SynchronizationCode::SYN_MT_REPORT.0,
+        EventType::KEY => buttons.contains(&event.code()) && !keymap::is_mouse_button(event.code()),
+        _ => false,
+    }
+}
+
+/// Leave out the events the virtual devices can't play, returning the rest
+/// and how many were left out
+///
+/// Raw macros keep everything the kernel reported, including events such as
+/// MSC_TIMESTAMP, LED changes or unusual relative axes that the virtual
+/// keyboard+mouse (and `abs_device`, if any) don't have.
+pub fn playable_events(
+    events: &[RecordedEvent],
+    abs_device: Option<&AbsDevice>,
+) -> (Vec<RecordedEvent>, usize) {
+    let is_playable = |event: &InputEvent| match event.event_type() {
+        EventType::SYNCHRONIZATION => true,
+        EventType::KEY => event.code() <= 0x2ff,
+        EventType::RELATIVE => RELATIVE_AXES.iter().any(|axis| axis.0 == event.code()),
+        EventType::MISC => MISC_CODES.iter().any(|code| code.0 == event.code()),
+        EventType::ABSOLUTE => abs_device.is_some_and(|abs_device| {
+            abs_device.axes.iter().any(|(code, _)| *code == event.code())
+        }),
+        _ => false,
+    };
+
+    let playable: Vec<RecordedEvent> = events
+        .iter()
+        .filter(|recorded| is_playable(&recorded.event))
+        .cloned()
+        .collect();
+    let skipped = events.len() - playable.len();
+    (playable, skipped)
+}
+
+/// Split events into frames at each SYN_REPORT
+///
+/// The SYN_REPORTs themselves are left out since `emit` terminates every
+/// write with one. Trailing events without a SYN_REPORT form a final frame.
+fn frames(events: &[RecordedEvent]) -> Vec<&[RecordedEvent]> {
+    events
+        .split(|recorded| {
+            recorded.event.event_type() == EventType::SYNCHRONIZATION
+                && recorded.event.code() == SynchronizationCode::SYN_REPORT.0
+        })
+        .filter(|frame| !frame.is_empty())
+        .collect()
+}
+
+/// The time `timestamp_us` microseconds after `start`, or as far after it as
+/// an `Instant` goes
+fn deadline_at(start: Instant, timestamp_us: u64) -> Instant {
+    let offset = Duration::from_micros(timestamp_us);
+    start.checked_add(offset).unwrap_or_else(|| {
+        // About a century, which any platform's Instant can hold
+        start + Duration::from_secs(3_153_600_000)
+    })
+}
+
+/// Work out when each frame should be played, in microseconds from the start
+///
+/// Every gap between frames is divided by the speed. Gaps while no key is
+/// held are idle waits and get capped at `max_wait`; gaps while keys are held
+/// are holds and keep their (scaled) length.
+fn schedule(frames: &[&[RecordedEvent]], timing: &Timing) -> Vec<u64> {
+    let max_wait_us = timing.max_wait.map(|max_wait| max_wait.as_micros() as u64);
+    let mut held_keys = HashSet::new();
+    let mut last_timestamp = 0u64;
+    let mut timestamp_us = 0u64;
+    let mut schedule = Vec::with_capacity(frames.len());
+
+    for frame in frames {
+        let gap = frame[0].timestamp_us.saturating_sub(last_timestamp);
+        // The cast saturates, so even a tiny speed can't wrap around
+        let mut scaled = (gap as f64 / timing.speed).round() as u64;
+        if held_keys.is_empty() {
+            scaled = max_wait_us.map_or(scaled, |max_wait| scaled.min(max_wait));
+        }
+        timestamp_us = timestamp_us.saturating_add(scaled);
+        schedule.push(timestamp_us);
+        last_timestamp = frame[0].timestamp_us;
+
+        for recorded in frame.iter().filter(|r| r.event.event_type() == EventType::KEY) {
+            match recorded.event.value() {
+                0 => {
+                    held_keys.remove(&recorded.event.code());
+                }
+                1 => {
+                    held_keys.insert(recorded.event.code());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    schedule
+}
+
+/// Move the events to the times they should be played at, then add or
+/// remove key repeats
+///
+/// Each frame keeps a single timestamp and is still closed by a SYN_REPORT.
+fn timed_events(
+    events: &[RecordedEvent],
+    timing: &Timing,
+    key_repeat: KeyRepeat,
+) -> Vec<RecordedEvent> {
+    let frames = frames(events);
+    let schedule = schedule(&frames, timing);
+
+    let mut timed = Vec::with_capacity(events.len());
+    for (frame, timestamp_us) in frames.into_iter().zip(schedule) {
+        for recorded in frame {
+            timed.push(RecordedEvent {
+                timestamp_us,
+                event: recorded.event,
+            });
+        }
+        timed.push(RecordedEvent {
+            timestamp_us,
+            event: InputEvent::new(EventType::SYNCHRONIZATION.0, 0, 0),
+        });
+    }
+
+    match key_repeat {
+        KeyRepeat::Recorded => timed,
+        key_repeat => key_repeat.apply(&timed),
+    }
+}
+
+/// Create a virtual device with the axes, buttons and properties of a
+/// recorded absolute-axis device
+fn build_abs_device(name: &str, abs_device: &AbsDevice) -> io::Result<VirtualDevice> {
+    let mut builder = VirtualDevice::builder()?.name(name);
+
+    // Games identify controllers by their IDs, so present the original ones
+    if let Some((bus, vendor, product, version)) = abs_device.id {
+        builder = builder.input_id(InputId::new(BusType(bus), vendor, product, version));
+    }
+
+    for &(code, info) in &abs_device.axes {
+        builder = builder.with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisCode(code), info))?;
+    }
+
+    if !abs_device.buttons.is_empty() {
+        let mut buttons = AttributeSet::<KeyCode>::new();
+        for &code in &abs_device.buttons {
+            buttons.insert(KeyCode(code));
+        }
+        builder = builder.with_keys(&buttons)?;
+    }
+
+    let mut properties = AttributeSet::<PropType>::new();
+    for &code in &abs_device.properties {
+        properties.insert(PropType(code));
+    }
+    builder = builder.with_properties(&properties)?;
+
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(timestamp_us: u64, event_type: EventType, code: u16, value: i32) -> RecordedEvent {
+        RecordedEvent {
+            timestamp_us,
+            event: InputEvent::new(event_type.0, code, value),
+        }
+    }
+
+    #[test]
+    fn test_routes_to_abs() {
+        let touchpad_buttons = [330, 325, 272]; // BTN_TOUCH, BTN_TOOL_FINGER, BTN_LEFT
+        let routed = |event_type: EventType, code| {
+            routes_to_abs(&InputEvent::new(event_type.0, code, 1), &touchpad_buttons)
+        };
+
+        assert!(routed(EventType::ABSOLUTE, 0));
+        assert!(routed(EventType::KEY, 330));
+        assert!(!routed(EventType::KEY, 272));
+        assert!(!routed(EventType::KEY, 30));
+        assert!(!routed(EventType::RELATIVE, 0));
+        assert!(routed(EventType::SYNCHRONIZATION, SynchronizationCode::SYN_MT_REPORT.0));
+        assert!(!routed(EventType::SYNCHRONIZATION, SynchronizationCode::SYN_REPORT.0));
+    }
+
+    #[test]
+    fn test_playable_events() {
+        let events = vec![
+            event(0, EventType::MISC, 4, 0x70004), // MSC_SCAN
+            event(0, EventType::KEY, 30, 1),
+            event(0, EventType::MISC, 5, 1_000), // MSC_TIMESTAMP
+            event(0, EventType::LED, 1, 1), // LED_CAPSL
+            event(0, EventType::RELATIVE, 11, 120), // REL_WHEEL_HI_RES
+            event(0, EventType::RELATIVE, 2, 1), // REL_Z
+            event(0, EventType::ABSOLUTE, 0, 100), // ABS_X
+            event(0, EventType::SYNCHRONIZATION, 0, 0),
+        ];
+        let types = |events: &[RecordedEvent]| -> Vec<(u16, u16)> {
+            events.iter().map(|e| (e.event.event_type().0, e.event.code())).collect()
+        };
+
+        let (playable, skipped) = playable_events(&events, None);
+        assert_eq!(skipped, 4);
+        assert_eq!(types(&playable), vec![(4, 4), (1, 30), (2, 11), (0, 0)]);
+
+        let tablet = AbsDevice {
+            axes: vec![(0, evdev::AbsInfo::new(0, 0, 4095, 0, 0, 0))],
+            ..AbsDevice::default()
+        };
+        let (playable, skipped) = playable_events(&events, Some(&tablet));
+        assert_eq!(skipped, 3);
+        assert_eq!(playable.len(), 5);
+    }
+
+    #[test]
+    fn test_playback_stats() {
+        let mut stats = PlaybackStats::default();
+        assert_eq!(stats.mean_lateness(), Duration::ZERO);
+
+        stats.record(Duration::from_micros(100));
+        stats.record(Duration::from_micros(500));
+        stats.record(Duration::from_micros(0));
+
+        assert_eq!(stats.frames, 3);
+        assert_eq!(stats.max_lateness, Duration::from_micros(500));
+        assert_eq!(stats.mean_lateness(), Duration::from_micros(200));
+        assert_eq!(
+            stats.to_string(),
+            "3 frames, lateness max 0.500ms, mean 0.200ms"
+        );
+    }
+
+    #[test]
+    fn test_schedule_speed_and_max_wait() {
+        let events = vec![
+            event(0, EventType::KEY, 17, 1), // W press
+            event(0, EventType::SYNCHRONIZATION, 0, 0),
+            event(2_000_000, EventType::KEY, 17, 0), // held for 2s
+            event(2_000_000, EventType::SYNCHRONIZATION, 0, 0),
+            event(5_000_000, EventType::KEY, 30, 1), // A press after 3s idle
+            event(5_000_000, EventType::SYNCHRONIZATION, 0, 0),
+            event(5_100_000, EventType::KEY, 30, 0),
+            event(5_100_000, EventType::SYNCHRONIZATION, 0, 0),
+        ];
+        let frames = frames(&events);
+
+        let normal = schedule(&frames, &Timing::default());
+        assert_eq!(normal, vec![0, 2_000_000, 5_000_000, 5_100_000]);
+
+        let fast = Timing { speed: 2.0, max_wait: None };
+        assert_eq!(schedule(&frames, &fast), vec![0, 1_000_000, 2_500_000, 2_550_000]);
+
+        // Only the idle gap is shortened, the holds keep their length
+        let capped = Timing {
+            speed: 1.0,
+            max_wait: Some(Duration::from_millis(500)),
+        };
+        assert_eq!(schedule(&frames, &capped), vec![0, 2_000_000, 2_500_000, 2_600_000]);
+    }
+
+    #[test]
+    fn test_schedule_tiny_speed_saturates() {
+        let events = vec![
+            event(0, EventType::KEY, 14, 1), // BACKSPACE held 2s
+            event(0, EventType::SYNCHRONIZATION, 0, 0),
+            event(2_000_000, EventType::KEY, 14, 0),
+            event(2_000_000, EventType::SYNCHRONIZATION, 0, 0),
+            event(5_000_000, EventType::KEY, 30, 1),
+            event(5_000_000, EventType::SYNCHRONIZATION, 0, 0),
+        ];
+        let slow = Timing { speed: 1e-15, max_wait: None };
+
+        let frames = frames(&events);
+        assert_eq!(schedule(&frames, &slow), vec![0, u64::MAX, u64::MAX]);
+
+        let timed = timed_events(&events, &slow, KeyRepeat::Off);
+        assert_eq!(timed.last().unwrap().timestamp_us, u64::MAX);
+        let start = Instant::now();
+        assert!(deadline_at(start, u64::MAX) > start);
+    }
+
+    #[test]
+    fn test_repeats_follow_speed() {
+        let events = vec![
+            event(0, EventType::KEY, 17, 1), // W held for 1s
+            event(0, EventType::SYNCHRONIZATION, 0, 0),
+            event(1_000_000, EventType::KEY, 17, 0),
+            event(1_000_000, EventType::SYNCHRONIZATION, 0, 0),
+        ];
+        let fast = Timing { speed: 2.0, max_wait: None };
+        let key_repeat = KeyRepeat::Synthesized {
+            delay: Duration::from_millis(250),
+            period: Duration::from_millis(100),
+        };
+
+        // Repeats at the usual rate within the halved hold
+        let keys: Vec<(u64, i32)> = timed_events(&events, &fast, key_repeat)
+            .iter()
+            .filter(|e| e.event.event_type() == EventType::KEY)
+            .map(|e| (e.timestamp_us, e.event.value()))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![(0, 1), (250_000, 2), (350_000, 2), (450_000, 2), (500_000, 0)]
+        );
+    }
+
+    #[test]
+    fn test_diagonal_move_is_one_frame() {
+        let events = vec![
+            event(0, EventType::RELATIVE, 0, 10), // REL_X
+            event(0, EventType::RELATIVE, 1, -5), // REL_Y
+            event(0, EventType::SYNCHRONIZATION, 0, 0),
+        ];
+
+        let frames = frames(&events);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].len(), 2);
+    }
+
+    #[test]
+    fn test_frames_split_at_syn_report() {
+        let events = vec![
+            event(0, EventType::KEY, 29, 1), // CTRL press
+            event(0, EventType::KEY, 46, 1), // C press, same frame
+            event(0, EventType::SYNCHRONIZATION, 0, 0),
+            event(0, EventType::SYNCHRONIZATION, 0, 0), // empty frame
+            event(50_000, EventType::ABSOLUTE, 0x35, 100), // MT_POSITION_X
+            event(50_000, EventType::SYNCHRONIZATION, 2, 0), // SYN_MT_REPORT stays in the frame
+            event(50_000, EventType::KEY, 46, 0), // C release, no SYN_REPORT
+        ];
+
+        let frames = frames(&events);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].len(), 2);
+        assert_eq!(frames[0][0].event.code(), 29);
+        assert_eq!(frames[0][1].event.code(), 46);
+        assert_eq!(frames[1].len(), 3);
+        assert_eq!(frames[1][0].timestamp_us, 50_000);
+    }
+}
@@ -0,0 +1,61 @@
+//! Capabilities of absolute-axis devices: tablets, touchscreens, touchpads
+//! and game controllers
+//!
+//! Saved alongside a macro so playback can create a virtual device with the
+//! same axis ranges, buttons and properties as the one it was recorded from.
+
+use crate::devices::DeviceKind;
+use crate::keymap;
+use evdev::{AbsInfo, Device};
+use std::io;
+
+/// Axes, buttons and properties of an absolute-axis device
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AbsDevice {
+    /// What sort of device this is
+    pub kind: Option<DeviceKind>,
+    /// Name of the real device
+    pub name: Option<String>,
+    /// Bus type, vendor, product and version of the real device; games look
+    /// controllers up by these
+    pub id: Option<(u16, u16, u16, u16)>,
+    /// Absolute axes with their ranges (the current value is not used)
+    pub axes: Vec<(u16, AbsInfo)>,
+    /// Buttons the device reports, e.g. BTN_TOUCH or BTN_TOOL_PEN (never
+    /// mouse buttons, which the virtual mouse plays)
+    pub buttons: Vec<u16>,
+    /// Input properties, e.g. INPUT_PROP_DIRECT for touchscreens
+    pub properties: Vec<u16>,
+}
+
+impl AbsDevice {
+    /// Read the capabilities of a real device
+    pub fn from_device(device: &Device, kind: DeviceKind) -> io::Result<Self> {
+        let id = device.input_id();
+        let axes = device
+            .get_absinfo()?
+            .map(|(axis, info)| (axis.0, info))
+            .collect();
+        // A touchpad's BTN_LEFT would otherwise claim every click of an
+        // external mouse, and play it back as a clickpad press
+        let buttons = device
+            .supported_keys()
+            .map(|keys| {
+                keys.iter()
+                    .map(|key| key.code())
+                    .filter(|&code| !keymap::is_mouse_button(code))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let properties = device.properties().iter().map(|prop| prop.0).collect();
+
+        Ok(Self {
+            kind: Some(kind),
+            name: device.name().map(str::to_string),
+            id: Some((id.bus_type().0, id.vendor(), id.product(), id.version())),
+            axes,
+            buttons,
+            properties,
+        })
+    }
+}
@@ -1,92 +1,334 @@
-//! Recording input events from keyboard and mouse
+//! Recording input events from keyboards, mice, absolute-axis devices and
+//! game controllers
 
-use evdev::{Device, InputEvent, EventSummary, KeyCode};
+use crate::devices::{self, DeviceFilter, DeviceKind};
+use crate::hotkey::Hotkey;
+use crate::profile::AbsDevice;
+use crate::signals::TerminationSignals;
+use evdev::{Device, EventSummary, EventType, InputEvent, KeyCode};
+use nix::errno::Errno;
+use nix::sys::epoll::{Epoll, EpollCreateFlags, EpollEvent, EpollFlags, EpollTimeout};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+use std::collections::HashSet;
+use std::fs;
 use std::io;
-use std::path::Path;
-use std::time::Instant;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+// EVIOCSCLOCKID: choose the clock used for event timestamps
+nix::ioctl_write_ptr!(eviocsclockid, b'E', 0xa0, nix::libc::c_int);
 
 /// Recorded event with relative timestamp
 #[derive(Debug, Clone)]
 pub struct RecordedEvent {
-    /// Time since recording started (in microseconds)
+    /// Time since the first recorded event (in microseconds)
     pub timestamp_us: u64,
     /// The actual input event
     pub event: InputEvent,
 }
 
+/// Hotkeys that control the recorder
+#[derive(Debug, Clone)]
+pub struct RecordHotkeys {
+    /// Start recording
+    pub start: Hotkey,
+    /// Stop recording and save
+    pub stop: Hotkey,
+    /// Pause or resume recording
+    pub pause: Hotkey,
+    /// Throw away everything recorded so far and start over
+    pub discard: Hotkey,
+}
+
+impl Default for RecordHotkeys {
+    fn default() -> Self {
+        Self {
+            start: Hotkey::default(),
+            stop: Hotkey::default(),
+            pause: Hotkey::parse("F2").expect("valid default hotkey"),
+            discard: Hotkey::parse("F3").expect("valid default hotkey"),
+        }
+    }
+}
+
+/// Epoll data for the inotify watch on /dev/input (device fds use their fd)
+const HOTPLUG_TOKEN: u64 = u64::MAX;
+/// Epoll data for SIGINT/SIGTERM
+const SIGNAL_TOKEN: u64 = u64::MAX - 1;
+
+/// How long to wait for keys to be released before grabbing a device
+const GRAB_RELEASE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A device being recorded from
+struct OpenDevice {
+    /// Device node, e.g. /dev/input/event3
+    path: PathBuf,
+    device: Device,
+    /// False for devices only watched for hotkeys
+    record: bool,
+    /// Whether this is the absolute-axis device being recorded
+    absolute: bool,
+}
+
+/// A change in recorder state caused by a hotkey
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    Started,
+    Paused,
+    Resumed,
+    Discarded,
+    Stopped,
+    /// SIGINT or SIGTERM received; recording has been abandoned
+    Interrupted,
+}
+
 pub struct Recorder {
-    devices: Vec<Device>,
-    start_time: Option<Instant>,
+    devices: Vec<OpenDevice>,
+    /// Wakes us when any device has input; each device is registered with
+    /// its fd as the event data
+    epoll: Epoll,
+    /// Watches /dev/input for devices being plugged in or removed
+    inotify: Inotify,
+    /// Watch on /dev/input/by-id, whose links can appear after the node
+    by_id_watch: Option<WatchDescriptor>,
+    /// Which newly connected devices to pick up
+    filter: DeviceFilter,
+    /// Ctrl-C and SIGTERM, so devices are released before exiting
+    signals: TerminationSignals,
+    /// Grab devices exclusively so input doesn't reach other applications
+    grab: bool,
+    /// Capabilities of the tablet, touchscreen, touchpad or controller being
+    /// recorded
+    abs_device: Option<AbsDevice>,
+    session: Session,
+}
+
+/// Hotkey handling and the recorded timeline, independent of the devices
+struct Session {
+    recording: bool,
+    /// Kernel timestamp that recorded timestamps are relative to: the first
+    /// recorded event, shifted forward by any time spent paused since
+    origin: Option<SystemTime>,
+    /// Kernel timestamp of the pause hotkey, if paused
+    paused_at: Option<SystemTime>,
     events: Vec<RecordedEvent>,
+    hotkeys: RecordHotkeys,
+    /// Keys currently held down across all devices
+    held_keys: HashSet<u16>,
+    /// Hotkey keys whose remaining events (repeats, releases) are swallowed
+    suppressed_keys: HashSet<u16>,
 }
 
 impl Recorder {
-    pub fn new() -> Self {
-        Self {
+    pub fn new(hotkeys: RecordHotkeys, filter: DeviceFilter, grab: bool) -> io::Result<Self> {
+        let epoll = Epoll::new(EpollCreateFlags::EPOLL_CLOEXEC)?;
+
+        let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)?;
+        inotify.add_watch(
+            "/dev/input",
+            AddWatchFlags::IN_CREATE | AddWatchFlags::IN_ATTRIB | AddWatchFlags::IN_DELETE,
+        )?;
+        // Only exists once a device with a serial has been connected
+        let by_id_watch = inotify
+            .add_watch("/dev/input/by-id", AddWatchFlags::IN_CREATE)
+            .ok();
+        epoll.add(&inotify, EpollEvent::new(EpollFlags::EPOLLIN, HOTPLUG_TOKEN))?;
+
+        let signals = TerminationSignals::new()?;
+        epoll.add(&signals, EpollEvent::new(EpollFlags::EPOLLIN, SIGNAL_TOKEN))?;
+
+        Ok(Self {
             devices: Vec::new(),
-            start_time: None,
-            events: Vec::new(),
-        }
+            epoll,
+            inotify,
+            by_id_watch,
+            filter,
+            signals,
+            grab,
+            abs_device: None,
+            session: Session::new(hotkeys),
+        })
     }
 
     /// Add a device to record from
     pub fn add_device<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let device = Device::open(path)?;
+        let name = device.name().unwrap_or("unknown").to_string();
+        self.insert_device(path, device, true)?;
+        println!("Added device: {}", name);
+        Ok(())
+    }
+
+    /// Add a device that is only watched for hotkeys, not recorded
+    ///
+    /// Used in gamepad mode, where the keyboard still controls the recorder.
+    pub fn add_control_device<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
         let device = Device::open(path)?;
+        let name = device.name().unwrap_or("unknown").to_string();
+        self.insert_device(path, device, false)?;
+        println!("Added hotkey device: {}", name);
+        Ok(())
+    }
+
+    /// Start reading from an opened device
+    fn insert_device(&mut self, path: &Path, mut device: Device, record: bool) -> io::Result<()> {
+        let absolute_kind = devices::device_kind(&device).filter(|kind| kind.is_absolute());
+        let abs_device = match (absolute_kind, record) {
+            (Some(kind), true) => Some(AbsDevice::from_device(&device, kind)?),
+            _ => None,
+        };
+        if let Some(abs_device) = &abs_device {
+            // Recorded events don't say which device they came from, so only
+            // one can be recreated on playback; it may reconnect, though
+            let other_open = self.devices.iter().any(|open| open.absolute);
+            let other_recorded = self
+                .abs_device
+                .as_ref()
+                .is_some_and(|d| (d.id, &d.name) != (abs_device.id, &abs_device.name));
+            if other_open || other_recorded {
+                return Err(io::Error::other(
+                    "only one tablet, touchscreen, touchpad or game controller can be recorded",
+                ));
+            }
+        }
+        if self.grab && record {
+            wait_for_key_release(&device);
+            device.grab()?;
+        }
         device.set_nonblocking(true)?;
-        println!("Added device: {}", device.name().unwrap_or("unknown"));
-        self.devices.push(device);
+        let fd = device.as_raw_fd();
+        // Use monotonic timestamps so wall-clock adjustments can't skew intervals
+        // SAFETY: `fd` stays open while `device` owns it, which outlives this
+        // call, and EVIOCSCLOCKID only reads the c_int behind the pointer
+        unsafe { eviocsclockid(fd, &nix::libc::CLOCK_MONOTONIC) }?;
+        self.epoll.add(&device, EpollEvent::new(EpollFlags::EPOLLIN, fd as u64))?;
+        let absolute = abs_device.is_some();
+        if absolute {
+            self.abs_device = abs_device;
+        }
+        self.devices.push(OpenDevice {
+            path: fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()),
+            device,
+            record,
+            absolute,
+        });
         Ok(())
     }
 
-    /// Start recording
-    pub fn start(&mut self) {
-        self.start_time = Some(Instant::now());
-        self.events.clear();
-        println!("Recording started...");
-    }
-
-    /// Poll all devices and record events
-    /// Returns true if recording state changed (started or stopped)
-    pub fn poll(&mut self) -> io::Result<bool> {
-        let mut state_changed = false;
-
-        for device in &mut self.devices {
-            match device.fetch_events() {
-                Ok(events) => {
-                    for event in events {
-                        if let EventSummary::Key(_, key, value) = event.destructure() {
-                            if key == KeyCode::KEY_F1 && value == 1 {
-                                // F1 pressed - toggle recording state
-                                println!("F1 key pressed!");
-                                if self.start_time.is_none() {
-                                    // Start recording
-                                    self.start_time = Some(Instant::now());
-                                    self.events.clear();
-                                    state_changed = true;
-                                } else {
-                                    // Stop recording
-                                    self.start_time = None;
-                                    state_changed = true;
-                                }
-                                continue; // Don't record the F1 press itself
-                            }
-                        }
-                        // Only record events if we're currently recording
-                        if let Some(start_time) = self.start_time {
-                            let elapsed = start_time.elapsed();
-                            let timestamp_us = elapsed.as_micros() as u64;
-
-                            self.events.push(RecordedEvent {
-                                timestamp_us,
-                                event,
-                            });
-                        }
-                    }
+    /// Stop reading from the device at `index`
+    fn remove_device(&mut self, index: usize) {
+        let removed = self.devices.remove(index);
+        // The fd may already be gone along with the device, so ignore errors
+        let _ = self.epoll.delete(&removed.device);
+        println!(
+            "Device disconnected: {} - {}",
+            removed.path.display(),
+            removed.device.name().unwrap_or("unknown")
+        );
+    }
+
+    /// Pick up newly connected devices and forget removed ones
+    fn handle_hotplug(&mut self) {
+        let Ok(changes) = self.inotify.read_events() else {
+            return;
+        };
+
+        for change in changes {
+            let Some(name) = change.name else {
+                continue;
+            };
+            let dir = if Some(change.wd) == self.by_id_watch {
+                "/dev/input/by-id"
+            } else {
+                "/dev/input"
+            };
+            let path = Path::new(dir).join(name);
+
+            if change.mask.contains(AddWatchFlags::IN_DELETE) {
+                if let Some(index) = self.devices.iter().position(|d| d.path == path) {
+                    self.remove_device(index);
                 }
+                continue;
+            }
+
+            // by-id entries are symlinks to the event node
+            let Ok(node) = fs::canonicalize(&path) else {
+                continue;
+            };
+            let is_event_node = node
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("event"));
+            if !is_event_node || self.devices.iter().any(|d| d.path == node) {
+                continue;
+            }
+
+            // Opening fails until udev has set permissions; a later IN_ATTRIB
+            // event retries
+            let Ok(device) = Device::open(&node) else {
+                continue;
+            };
+            // Keyboards that aren't recorded still carry the hotkeys, as at
+            // startup
+            let record = self.filter.matches(&path, &device);
+            let is_keyboard = matches!(
+                devices::device_kind(&device),
+                Some(DeviceKind::Keyboard | DeviceKind::KeyboardMouse)
+            );
+            if !record && !is_keyboard {
+                continue;
+            }
+
+            let name = device.name().unwrap_or("unknown").to_string();
+            match (self.insert_device(&node, device, record), record) {
+                (Ok(()), true) => println!("Device connected: {} - {}", node.display(), name),
+                (Ok(()), false) => {
+                    println!("Hotkey device connected: {} - {}", node.display(), name);
+                }
+                (Err(e), _) => {
+                    eprintln!("Warning: Could not add device {}: {}", node.display(), e);
+                }
+            }
+        }
+    }
+
+    /// Block until any device has input, then record its events
+    /// Returns the state transitions triggered by hotkeys, in order
+    pub fn poll(&mut self) -> io::Result<Vec<Transition>> {
+        let mut ready = [EpollEvent::empty(); 16];
+        let count = match self.epoll.wait(&mut ready, EpollTimeout::NONE) {
+            Ok(count) => count,
+            Err(Errno::EINTR) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let ready_fds: Vec<u64> = ready[..count].iter().map(|e| e.data()).collect();
+
+        if ready_fds.contains(&SIGNAL_TOKEN) && self.signals.received() {
+            self.session.end();
+            self.release_devices();
+            return Ok(vec![Transition::Interrupted]);
+        }
+
+        let mut fetched = Vec::new();
+        let mut unplugged = Vec::new();
+
+        for (index, open) in self.devices.iter_mut().enumerate() {
+            if !ready_fds.contains(&(open.device.as_raw_fd() as u64)) {
+                continue;
+            }
+
+            match open.device.fetch_events() {
+                Ok(events) => fetched.extend(events.map(|event| (event, open.record))),
                 Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
                     // Don't error if there are no events polled
                     continue;
                 }
+                Err(ref e) if e.raw_os_error() == Some(Errno::ENODEV as i32) => {
+                    unplugged.push(index);
+                }
                 Err(e) => {
                     eprintln!("Device read error: {}", e);
                 }
@@ -94,23 +336,430 @@ impl Recorder {
             }
         }
 
-        Ok(state_changed)
-    }
+        for index in unplugged.into_iter().rev() {
+            self.remove_device(index);
+        }
+        if ready_fds.contains(&HOTPLUG_TOKEN) {
+            self.handle_hotplug();
+        }
 
-    /// Check if currently recording
-    pub fn is_recording(&self) -> bool {
-        self.start_time.is_some()
+        // Merge events from all devices in the order the kernel saw them
+        fetched.sort_by_key(|(event, _)| event.timestamp());
+
+        let mut transitions = Vec::new();
+        for (event, record) in fetched {
+            if let Some(transition) = self.session.handle_event(event, record) {
+                transitions.push(transition);
+            }
+        }
+
+        Ok(transitions)
     }
 
     /// Stop recording and return recorded events
     pub fn stop(&mut self) -> Vec<RecordedEvent> {
-        self.start_time = None;
-        println!("Recording stopped. Recorded {} events", self.events.len());
-        std::mem::take(&mut self.events)
+        self.session.end();
+        self.release_devices();
+        let mut events = std::mem::take(&mut self.session.events);
+        println!("Recording stopped. Recorded {} events", events.len());
+        // Devices are read one at a time, so restore chronological order
+        events.sort_by_key(|e| e.timestamp_us);
+        events
+    }
+
+    /// Capabilities of the absolute-axis device recorded from, if any
+    pub fn abs_device(&self) -> Option<&AbsDevice> {
+        self.abs_device.as_ref()
     }
 
     /// Get currently recorded events without stopping
     pub fn events(&self) -> &[RecordedEvent] {
-        &self.events
+        &self.session.events
+    }
+
+    /// Release exclusive grabs so input reaches other applications again
+    fn release_devices(&mut self) {
+        if !self.grab {
+            return;
+        }
+        for open in self.devices.iter_mut().filter(|open| open.record) {
+            // Nothing more we can do if this fails; closing the fd releases it too
+            let _ = open.device.ungrab();
+        }
+        self.grab = false;
+    }
+}
+
+impl Session {
+    fn new(hotkeys: RecordHotkeys) -> Self {
+        Self {
+            recording: false,
+            origin: None,
+            paused_at: None,
+            events: Vec::new(),
+            hotkeys,
+            held_keys: HashSet::new(),
+            suppressed_keys: HashSet::new(),
+        }
+    }
+
+    /// Start (or restart) recording with an empty timeline
+    ///
+    /// Keys already down have no press in the new timeline, so their release
+    /// (and any repeats before it) isn't recorded either.
+    fn start(&mut self) {
+        self.recording = true;
+        self.origin = None;
+        self.paused_at = None;
+        self.events.clear();
+        self.suppressed_keys.extend(self.held_keys.iter().copied());
+    }
+
+    /// Stop recording, keeping what was recorded
+    fn end(&mut self) {
+        self.recording = false;
+        self.paused_at = None;
+    }
+
+    /// Check if currently recording (including while paused)
+    fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Check if recording is paused
+    fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    /// Process a single input event, from a recorded device if `record`
+    /// Returns the transition if it triggered a hotkey
+    fn handle_event(&mut self, event: InputEvent, record: bool) -> Option<Transition> {
+        if let EventSummary::Key(_, KeyCode(code), value) = event.destructure() {
+            match value {
+                1 => {
+                    self.held_keys.insert(code);
+                }
+                0 => {
+                    self.held_keys.remove(&code);
+                }
+                _ => {}
+            }
+
+            if self.suppressed_keys.contains(&code) {
+                // Don't record the rest of the hotkey chord
+                if value == 0 {
+                    self.suppressed_keys.remove(&code);
+                }
+                return None;
+            }
+
+            if value == 1 {
+                if let Some((transition, hotkey)) = self.triggered_hotkey(code) {
+                    if self.is_recording() && !self.is_paused() {
+                        // Modifiers of the chord were pressed while recording
+                        self.strip_chord_presses(&hotkey, code);
+                    }
+                    self.apply(transition, event.timestamp());
+                    self.suppressed_keys.extend(hotkey.keys());
+                    return Some(transition);
+                }
+            }
+        }
+
+        // Only record events if we're currently recording
+        if record && self.recording && self.paused_at.is_none() {
+            let origin = *self.origin.get_or_insert(event.timestamp());
+            let timestamp_us = event
+                .timestamp()
+                .duration_since(origin)
+                .unwrap_or_default()
+                .as_micros() as u64;
+
+            self.events.push(RecordedEvent {
+                timestamp_us,
+                event,
+            });
+        }
+
+        None
+    }
+
+    /// Find the hotkey completed by pressing `code`, if any
+    ///
+    /// Start and stop may share a chord, so the current state decides which
+    /// one applies.
+    fn triggered_hotkey(&self, code: u16) -> Option<(Transition, Hotkey)> {
+        let hotkeys = &self.hotkeys;
+        let held = &self.held_keys;
+
+        if !self.is_recording() {
+            return hotkeys
+                .start
+                .is_triggered(code, held)
+                .then(|| (Transition::Started, hotkeys.start.clone()));
+        }
+
+        if hotkeys.stop.is_triggered(code, held) {
+            Some((Transition::Stopped, hotkeys.stop.clone()))
+        } else if hotkeys.pause.is_triggered(code, held) {
+            let transition = if self.is_paused() {
+                Transition::Resumed
+            } else {
+                Transition::Paused
+            };
+            Some((transition, hotkeys.pause.clone()))
+        } else if hotkeys.discard.is_triggered(code, held) {
+            Some((Transition::Discarded, hotkeys.discard.clone()))
+        } else {
+            None
+        }
+    }
+
+    /// Update recorder state for a transition
+    fn apply(&mut self, transition: Transition, time: SystemTime) {
+        match transition {
+            Transition::Started | Transition::Discarded => self.start(),
+            Transition::Paused => self.paused_at = Some(time),
+            Transition::Resumed => {
+                // Shift the timeline so the paused time isn't recorded as a wait
+                if let (Some(origin), Some(paused_at)) = (self.origin, self.paused_at.take()) {
+                    let origin = origin + time.duration_since(paused_at).unwrap_or_default();
+                    self.origin = Some(origin);
+                    let timestamp_us = time.duration_since(origin).unwrap_or_default().as_micros();
+                    self.release_keys_let_go(timestamp_us as u64);
+                }
+
+                // Keys pressed while paused have no press in the recording, so
+                // as in `start`, their release isn't recorded either
+                let down = self.recorded_keys_down();
+                let paused_presses = self.held_keys.iter().filter(|code| !down.contains(code));
+                self.suppressed_keys.extend(paused_presses);
+            }
+            Transition::Stopped | Transition::Interrupted => self.end(),
+        }
+    }
+
+    /// Record releases of keys that are down in the recording but were let
+    /// go while paused, so they don't stay held for the rest of the macro
+    fn release_keys_let_go(&mut self, timestamp_us: u64) {
+        let mut down = self.recorded_keys_down();
+        down.retain(|code| !self.held_keys.contains(code));
+        if down.is_empty() {
+            return;
+        }
+
+        for code in down.into_iter().rev() {
+            self.events.push(RecordedEvent {
+                timestamp_us,
+                event: InputEvent::new(EventType::KEY.0, code, 0),
+            });
+        }
+        self.events.push(RecordedEvent {
+            timestamp_us,
+            event: InputEvent::new(EventType::SYNCHRONIZATION.0, 0, 0),
+        });
+    }
+
+    /// Keys pressed and not yet released in the recording, in press order
+    fn recorded_keys_down(&self) -> Vec<u16> {
+        let mut down: Vec<u16> = Vec::new();
+        for recorded in &self.events {
+            if recorded.event.event_type() != EventType::KEY {
+                continue;
+            }
+            let code = recorded.event.code();
+            if recorded.event.value() == 0 {
+                down.retain(|&key| key != code);
+            } else if !down.contains(&code) {
+                down.push(code);
+            }
+        }
+        down
+    }
+
+    /// Remove already-recorded presses of the hotkey's other keys
+    ///
+    /// The chord keys are all held when the hotkey triggers, so the last
+    /// press of each one (and any repeats after it) belongs to the chord.
+    fn strip_chord_presses(&mut self, hotkey: &Hotkey, trigger: u16) {
+        for &code in hotkey.keys() {
+            if code == trigger {
+                continue;
+            }
+
+            let is_key = |e: &RecordedEvent| {
+                e.event.event_type() == EventType::KEY && e.event.code() == code
+            };
+            let last_press = self
+                .events
+                .iter()
+                .rposition(|e| is_key(e) && e.event.value() == 1);
+
+            if let Some(index) = last_press {
+                let tail = self.events.split_off(index);
+                self.events.extend(tail.into_iter().filter(|e| !is_key(e)));
+            }
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // Also runs when unwinding from a panic
+        self.release_devices();
+    }
+}
+
+/// Wait (briefly) until no keys are held on `device`
+///
+/// Grabbing a device while a key is down means its release never reaches
+/// other applications, leaving the key stuck for them - typically the Enter
+/// used to start evkey.
+fn wait_for_key_release(device: &Device) {
+    let deadline = Instant::now() + GRAB_RELEASE_TIMEOUT;
+    while Instant::now() < deadline {
+        match device.get_key_state() {
+            Ok(keys) if keys.iter().next().is_some() => thread::sleep(Duration::from_millis(10)),
+            _ => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::libc;
+
+    fn key(code: u16, value: i32) -> InputEvent {
+        InputEvent::new(EventType::KEY.0, code, value)
+    }
+
+    /// Key event with a kernel timestamp `ms` milliseconds after the epoch
+    fn key_at(ms: i64, code: u16, value: i32) -> InputEvent {
+        InputEvent::from(libc::input_event {
+            time: libc::timeval {
+                tv_sec: ms / 1000,
+                tv_usec: ms % 1000 * 1000,
+            },
+            type_: EventType::KEY.0,
+            code,
+            value,
+        })
+    }
+
+    /// (code, value) of the recorded events
+    fn keys(session: &Session) -> Vec<(u16, i32)> {
+        session
+            .events
+            .iter()
+            .map(|e| (e.event.code(), e.event.value()))
+            .collect()
+    }
+
+    #[test]
+    fn test_pause_chord_keeps_recorded_modifier() {
+        let hotkeys = RecordHotkeys {
+            pause: Hotkey::parse("CTRL+ALT+P").unwrap(),
+            ..RecordHotkeys::default()
+        };
+        let mut session = Session::new(hotkeys);
+        session.start();
+
+        // CTRL+C
+        for (code, value) in [(29, 1), (46, 1), (46, 0), (29, 0)] {
+            assert_eq!(session.handle_event(key(code, value), true), None);
+        }
+
+        // Pause and resume with the chord; nothing pressed in between is recorded
+        for transition in [Transition::Paused, Transition::Resumed] {
+            session.handle_event(key(29, 1), true);
+            session.handle_event(key(56, 1), true);
+            assert_eq!(session.handle_event(key(25, 1), true), Some(transition));
+            for code in [25, 56, 29] {
+                session.handle_event(key(code, 0), true);
+            }
+        }
+
+        assert!(!session.is_paused());
+        assert_eq!(keys(&session), [(29, 1), (46, 1), (46, 0), (29, 0)]);
+
+        // W held when pausing, let go while paused
+        session.handle_event(key(17, 1), true);
+        session.handle_event(key(29, 1), true);
+        session.handle_event(key(56, 1), true);
+        session.handle_event(key(25, 1), true);
+        for code in [25, 56, 29] {
+            session.handle_event(key(code, 0), true);
+        }
+        session.handle_event(key(17, 0), true);
+        session.handle_event(key(29, 1), true);
+        session.handle_event(key(56, 1), true);
+        assert_eq!(session.handle_event(key(25, 1), true), Some(Transition::Resumed));
+
+        // Released when recording resumes
+        let release = &session.events[session.events.len() - 2];
+        assert_eq!((release.event.code(), release.event.value()), (17, 0));
+        assert_eq!(session.events.last().unwrap().event.event_type(), EventType::SYNCHRONIZATION);
+    }
+
+    #[test]
+    fn test_discard_drops_release_of_held_key() {
+        let mut session = Session::new(RecordHotkeys::default());
+        session.start();
+
+        // W held across the discard hotkey (F3)
+        session.handle_event(key(17, 1), true);
+        assert_eq!(session.handle_event(key(61, 1), true), Some(Transition::Discarded));
+        session.handle_event(key(61, 0), true);
+        session.handle_event(key(17, 2), true);
+        session.handle_event(key(17, 0), true);
+
+        // The new recording starts with A, not a lone W release
+        session.handle_event(key(30, 1), true);
+        session.handle_event(key(30, 0), true);
+        assert_eq!(keys(&session), [(30, 1), (30, 0)]);
+    }
+
+    #[test]
+    fn test_resume_drops_release_of_key_pressed_while_paused() {
+        let mut session = Session::new(RecordHotkeys::default());
+        session.start();
+
+        session.handle_event(key(30, 1), true);
+        session.handle_event(key(30, 0), true);
+        // W pressed while paused (F2) and still held when resuming
+        assert_eq!(session.handle_event(key(60, 1), true), Some(Transition::Paused));
+        session.handle_event(key(60, 0), true);
+        session.handle_event(key(17, 1), true);
+        assert_eq!(session.handle_event(key(60, 1), true), Some(Transition::Resumed));
+        session.handle_event(key(60, 0), true);
+        session.handle_event(key(17, 2), true);
+        session.handle_event(key(17, 0), true);
+
+        assert_eq!(keys(&session), [(30, 1), (30, 0)]);
+    }
+
+    #[test]
+    fn test_timestamps_from_first_event_without_pauses() {
+        let mut session = Session::new(RecordHotkeys::default());
+        session.start();
+
+        session.handle_event(key_at(1000, 17, 1), true);
+        session.handle_event(key_at(1100, 17, 0), true);
+        // Paused (F2) for two seconds
+        assert_eq!(session.handle_event(key_at(1500, 60, 1), true), Some(Transition::Paused));
+        session.handle_event(key_at(1600, 60, 0), true);
+        assert_eq!(session.handle_event(key_at(3500, 60, 1), true), Some(Transition::Resumed));
+        session.handle_event(key_at(3600, 60, 0), true);
+        session.handle_event(key_at(3700, 30, 1), true);
+        let times: Vec<u64> = session.events.iter().map(|e| e.timestamp_us).collect();
+        assert_eq!(times, [0, 100_000, 700_000]);
+
+        // Discarding (F3) starts the timeline over
+        session.handle_event(key_at(4000, 30, 0), true);
+        assert_eq!(session.handle_event(key_at(5000, 61, 1), true), Some(Transition::Discarded));
+        session.handle_event(key_at(5100, 61, 0), true);
+        session.handle_event(key_at(6000, 17, 1), true);
+        assert_eq!(session.events[0].timestamp_us, 0);
     }
 }
@@ -0,0 +1,185 @@
+//! Playing a macro more than once
+//!
+//! Set with `--loop`, `--repeat`, `--interval` and `--until` on the command
+//! line, or with the same settings at the top of a macro file:
+//!   repeat 5
+//!   interval 2s
+//!   until 17:30
+
+use crate::storage::parse_duration;
+use nix::libc;
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How many times to play a macro
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runs {
+    Times(u32),
+    Forever,
+}
+
+/// Repeat settings; unset fields fall back to another source or the default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Repeat {
+    pub runs: Option<Runs>,
+    /// Pause between the end of one run and the start of the next
+    pub interval: Option<Duration>,
+    /// Local time of day (hour, minute) after which no new run starts
+    pub until: Option<(u8, u8)>,
+}
+
+impl Repeat {
+    /// Combine with settings that take precedence, e.g. the command line over
+    /// the macro file
+    pub fn overridden_by(self, other: Repeat) -> Repeat {
+        Repeat {
+            runs: other.runs.or(self.runs),
+            interval: other.interval.or(self.interval),
+            until: other.until.or(self.until),
+        }
+    }
+
+    /// Number of runs, or None to keep going until stopped or `until`
+    ///
+    /// A macro plays once by default, or repeatedly if only a deadline is set.
+    pub fn limit(&self) -> Option<u32> {
+        match self.runs {
+            Some(Runs::Times(n)) => Some(n),
+            Some(Runs::Forever) => None,
+            None if self.until.is_some() => None,
+            None => Some(1),
+        }
+    }
+
+    /// Apply a `repeat`, `interval` or `until` setting from a macro file
+    /// Returns None if the line isn't a repeat setting
+    pub fn parse_line(&mut self, line: &str) -> Option<Result<(), String>> {
+        let (setting, value) = line.split_once(' ')?;
+        let value = value.trim();
+
+        let result = match setting {
+            "repeat" => parse_runs(value).map(|runs| self.runs = Some(runs)),
+            "interval" => parse_duration(value).map(|interval| self.interval = Some(interval)),
+            "until" => parse_time_of_day(value).map(|time| self.until = Some(time)),
+            _ => return None,
+        };
+        Some(result)
+    }
+}
+
+/// Parse a run count: a positive number or "forever"
+pub fn parse_runs(s: &str) -> Result<Runs, String> {
+    if s == "forever" {
+        return Ok(Runs::Forever);
+    }
+    match s.parse::<u32>() {
+        Ok(n) if n > 0 => Ok(Runs::Times(n)),
+        _ => Err(format!("Invalid repeat count: {}", s)),
+    }
+}
+
+/// Parse a 24-hour time like "17:30"
+pub fn parse_time_of_day(s: &str) -> Result<(u8, u8), String> {
+    let invalid = || format!("Time must be HH:MM: {}", s);
+    let (hour, minute) = s.split_once(':').ok_or_else(invalid)?;
+    let hour: u8 = hour.parse().map_err(|_| invalid())?;
+    let minute: u8 = minute.parse().map_err(|_| invalid())?;
+
+    if hour > 23 || minute > 59 || s.len() != 5 {
+        return Err(invalid());
+    }
+    Ok((hour, minute))
+}
+
+/// The next time the local clock shows `hour:minute`, today or tomorrow
+pub fn next_time_of_day((hour, minute): (u8, u8)) -> io::Result<SystemTime> {
+    // SAFETY: time() accepts a null pointer and then only returns the time
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+
+    // SAFETY: tm is plain integers (and a pointer that may be null), so all
+    // zeroes is a valid value
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    // SAFETY: both pointers come from references to live locals, and the
+    // reentrant localtime_r writes only to `tm`
+    if unsafe { libc::localtime_r(&now, &mut tm) }.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    tm.tm_hour = hour as i32;
+    tm.tm_min = minute as i32;
+    tm.tm_sec = 0;
+    // Let mktime work out whether daylight saving applies at that time
+    tm.tm_isdst = -1;
+
+    // SAFETY: `tm` is a valid, initialized tm that mktime may normalize
+    let mut target = unsafe { libc::mktime(&mut tm) };
+    if target <= now {
+        // mktime normalizes day 32 and the like, and DST changes overnight
+        tm.tm_mday += 1;
+        tm.tm_isdst = -1;
+        // SAFETY: as above
+        target = unsafe { libc::mktime(&mut tm) };
+    }
+    if target == -1 {
+        return Err(io::Error::other("Could not convert local time"));
+    }
+
+    Ok(UNIX_EPOCH + Duration::from_secs(target as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit() {
+        assert_eq!(Repeat::default().limit(), Some(1));
+
+        let until = Repeat {
+            until: Some((17, 30)),
+            ..Repeat::default()
+        };
+        assert_eq!(until.limit(), None);
+
+        let times = Repeat {
+            runs: Some(Runs::Times(3)),
+            ..until
+        };
+        assert_eq!(times.limit(), Some(3));
+    }
+
+    #[test]
+    fn test_parse_lines_and_override() {
+        let mut file = Repeat::default();
+        assert_eq!(file.parse_line("repeat forever"), Some(Ok(())));
+        assert_eq!(file.parse_line("interval 2s"), Some(Ok(())));
+        assert_eq!(file.parse_line("until 07:05"), Some(Ok(())));
+        assert!(file.parse_line("hold W for 10ms").is_none());
+        assert!(file.parse_line("repeat 0").unwrap().is_err());
+
+        let cli = Repeat {
+            runs: Some(Runs::Times(3)),
+            ..Repeat::default()
+        };
+        let combined = file.overridden_by(cli);
+        assert_eq!(combined.runs, Some(Runs::Times(3)));
+        assert_eq!(combined.interval, Some(Duration::from_secs(2)));
+        assert_eq!(combined.until, Some((7, 5)));
+    }
+
+    #[test]
+    fn test_parse_time_of_day() {
+        assert_eq!(parse_time_of_day("17:30").unwrap(), (17, 30));
+        assert_eq!(parse_time_of_day("00:00").unwrap(), (0, 0));
+        assert!(parse_time_of_day("24:00").is_err());
+        assert!(parse_time_of_day("7:30").is_err());
+        assert!(parse_time_of_day("17.30").is_err());
+    }
+
+    #[test]
+    fn test_next_time_of_day_is_within_a_day() {
+        let next = next_time_of_day((12, 0)).unwrap();
+        let until = next.duration_since(SystemTime::now()).unwrap();
+        // A DST change can make a day 25 hours long
+        assert!(until <= Duration::from_secs(25 * 3600));
+    }
+}
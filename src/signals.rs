@@ -0,0 +1,46 @@
+//! Catching SIGINT and SIGTERM so we can clean up before exiting
+//!
+//! The signals are blocked and delivered through a signalfd instead, which
+//! can be waited on with epoll next to the input devices.
+
+use nix::sys::signal::{SigSet, Signal};
+use nix::sys::signalfd::{SfdFlags, SignalFd};
+use std::io;
+use std::os::fd::{AsFd, BorrowedFd};
+
+/// SIGINT/SIGTERM, readable as a file descriptor while this is alive
+pub struct TerminationSignals {
+    fd: SignalFd,
+    mask: SigSet,
+}
+
+impl TerminationSignals {
+    /// Block SIGINT and SIGTERM and receive them through a file descriptor
+    pub fn new() -> io::Result<Self> {
+        let mut mask = SigSet::empty();
+        mask.add(Signal::SIGINT);
+        mask.add(Signal::SIGTERM);
+        mask.thread_block()?;
+
+        let fd = SignalFd::with_flags(&mask, SfdFlags::SFD_NONBLOCK | SfdFlags::SFD_CLOEXEC)?;
+        Ok(Self { fd, mask })
+    }
+
+    /// Check for a pending signal, consuming it
+    pub fn received(&self) -> bool {
+        matches!(self.fd.read_signal(), Ok(Some(_)))
+    }
+}
+
+impl AsFd for TerminationSignals {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl Drop for TerminationSignals {
+    fn drop(&mut self) {
+        // Restore the default behaviour (terminate) for the rest of the run
+        let _ = self.mask.thread_unblock();
+    }
+}
@@ -3,30 +3,35 @@
 //! Converts low-level input events into high-level "states" representing
 //! which keys are pressed for how long. This enables human-readable macros.
 
+use crate::keymap;
 use crate::recorder::RecordedEvent;
 use evdev::{EventType, InputEvent};
-use std::collections::HashSet;
 
 /// A macro state: which keys are held and for how long
 #[derive(Debug, Clone, PartialEq)]
 pub struct MacroState {
-    /// Duration this state lasts (in milliseconds)
-    pub duration_ms: u64,
-    /// Keys that are pressed during this state (Linux keycodes)
-    pub keys_pressed: HashSet<u16>,
+    /// Duration this state lasts (in microseconds)
+    pub duration_us: u64,
+    /// Keys that are pressed during this state (Linux keycodes), in the
+    /// order they went down
+    pub keys_pressed: Vec<u16>,
     /// Mouse movement during this state (relative x, y)
     pub mouse_delta: (i32, i32),
     /// Mouse scroll during this state (vertical, horizontal)
     pub scroll_delta: (i32, i32),
+    /// Absolute axis values set at the start of this state (axis code, value),
+    /// in the order they happened so multitouch slot switches stay meaningful
+    pub abs_changes: Vec<(u16, i32)>,
 }
 
 impl MacroState {
-    pub fn new(duration_ms: u64) -> Self {
+    pub fn new(duration_us: u64) -> Self {
         Self {
-            duration_ms,
-            keys_pressed: HashSet::new(),
+            duration_us,
+            keys_pressed: Vec::new(),
             mouse_delta: (0, 0),
             scroll_delta: (0, 0),
+            abs_changes: Vec::new(),
         }
     }
 
@@ -35,38 +40,58 @@ impl MacroState {
         self.keys_pressed.is_empty()
             && self.mouse_delta == (0, 0)
             && self.scroll_delta == (0, 0)
+            && self.abs_changes.is_empty()
+    }
+}
+
+/// How mouse movement is tidied up when events become states
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MouseFilter {
+    /// Movement shorter than this (|x| + |y|, in pixels) doesn't get a `move`
+    /// of its own; it is added to the next state's movement instead
+    pub threshold: i32,
+    /// Merge mouse moves as long as the simplified path stays within this
+    /// many pixels of the recorded one (Ramer-Douglas-Peucker), if set
+    pub simplify: Option<f64>,
+}
+
+impl Default for MouseFilter {
+    fn default() -> Self {
+        Self {
+            threshold: 5,
+            simplify: None,
+        }
     }
 }
 
 /// Convert recorded events into state-based representation
-pub fn events_to_states(events: &[RecordedEvent]) -> Vec<MacroState> {
+pub fn events_to_states(events: &[RecordedEvent], mouse: &MouseFilter) -> Vec<MacroState> {
     if events.is_empty() {
         return Vec::new();
     }
 
     let mut states = Vec::new();
-    let mut current_keys: HashSet<u16> = HashSet::new();
+    let mut current_keys: Vec<u16> = Vec::new();
     let mut last_timestamp_us = 0u64;
     let mut accumulated_mouse = (0i32, 0i32);
     let mut accumulated_scroll = (0i32, 0i32);
+    let mut accumulated_abs: Vec<(u16, i32)> = Vec::new();
 
     for event in events {
         let elapsed_us = event.timestamp_us.saturating_sub(last_timestamp_us);
 
         // If time has passed, save the current state (even if empty - that's a wait)
         if elapsed_us > 0 {
-            let duration_ms = elapsed_us / 1000; // Convert microseconds to milliseconds
-            if duration_ms > 0 {
-                let mut state = MacroState::new(duration_ms);
-                state.keys_pressed = current_keys.clone();
-                state.mouse_delta = accumulated_mouse;
-                state.scroll_delta = accumulated_scroll;
-                states.push(state);
-
-                // Reset mouse and scroll accumulators after saving
-                accumulated_mouse = (0, 0);
-                accumulated_scroll = (0, 0);
-            }
+            let mut state = MacroState::new(elapsed_us);
+            state.keys_pressed = current_keys.clone();
+            state.mouse_delta = accumulated_mouse;
+            state.scroll_delta = accumulated_scroll;
+            state.abs_changes = std::mem::take(&mut accumulated_abs);
+            states.push(state);
+
+            // Reset mouse and scroll accumulators after saving
+            accumulated_mouse = (0, 0);
+            accumulated_scroll = (0, 0);
         }
 
         // Process the event
@@ -76,13 +101,13 @@ pub fn events_to_states(events: &[RecordedEvent]) -> Vec<MacroState> {
                 let value = event.event.value();
 
                 match value {
-                    1 => {
+                    1 if !current_keys.contains(&key_code) => {
                         // Key press
-                        current_keys.insert(key_code);
+                        current_keys.push(key_code);
                     }
                     0 => {
                         // Key release
-                        current_keys.remove(&key_code);
+                        current_keys.retain(|&code| code != key_code);
                     }
                     _ => {
                         // Ignore key repeat (value 2)
@@ -102,6 +127,10 @@ pub fn events_to_states(events: &[RecordedEvent]) -> Vec<MacroState> {
                     _ => {}
                 }
             }
+            EventType::ABSOLUTE => {
+                // Tablet, touchscreen and touchpad positions, pressure, slots
+                accumulated_abs.push((event.event.code(), event.event.value()));
+            }
             _ => {
                 // Ignore sync and other event types for state tracking
             }
@@ -111,26 +140,205 @@ pub fn events_to_states(events: &[RecordedEvent]) -> Vec<MacroState> {
     }
 
     // Add final state if keys are still pressed or actions remain
-    if !current_keys.is_empty() || accumulated_mouse != (0, 0) || accumulated_scroll != (0, 0) {
+    if !current_keys.is_empty()
+        || accumulated_mouse != (0, 0)
+        || accumulated_scroll != (0, 0)
+        || !accumulated_abs.is_empty()
+    {
         let mut state = MacroState::new(0); // Final state with no duration
         state.keys_pressed = current_keys;
         state.mouse_delta = accumulated_mouse;
         state.scroll_delta = accumulated_scroll;
+        state.abs_changes = accumulated_abs;
         states.push(state);
     }
 
-    // Filter out small mouse movements (< 5px) from all states
-    for state in &mut states {
-        let distance = state.mouse_delta.0.abs() + state.mouse_delta.1.abs();
-        if distance < 5 {
-            state.mouse_delta = (0, 0);
-        }
+    if let Some(tolerance) = mouse.simplify {
+        states = simplify_paths(states, tolerance);
     }
+    carry_small_moves(&mut states, mouse.threshold);
 
     // Merge consecutive identical states
     merge_consecutive_states(states)
 }
 
+/// Hold back mouse movement below `threshold` until it adds up to more
+///
+/// Nothing is dropped: whatever is held back is moved before the next key
+/// change, so clicks land where they were recorded, and at the end, so the
+/// pointer ends up where it did.
+fn carry_small_moves(states: &mut [MacroState], threshold: i32) {
+    let mut carried = (0, 0);
+
+    for index in 0..states.len() {
+        let keys_change = states
+            .get(index + 1)
+            .is_none_or(|next| next.keys_pressed != states[index].keys_pressed);
+
+        let state = &mut states[index];
+        let delta = (
+            state.mouse_delta.0 + carried.0,
+            state.mouse_delta.1 + carried.1,
+        );
+        if delta.0.abs() + delta.1.abs() < threshold && !keys_change {
+            state.mouse_delta = (0, 0);
+            carried = delta;
+        } else {
+            state.mouse_delta = delta;
+            carried = (0, 0);
+        }
+    }
+}
+
+/// Check if a state only moves the mouse (keys may be held, as in a drag)
+fn is_plain_move(state: &MacroState) -> bool {
+    state.mouse_delta != (0, 0) && state.scroll_delta == (0, 0) && state.abs_changes.is_empty()
+}
+
+/// Shorten runs of mouse moves, keeping the path within `tolerance` pixels
+///
+/// Each run of back-to-back moves with the same keys held is simplified on
+/// its own. Merged moves add up to the same total, so the pointer still ends
+/// up exactly where it was recorded.
+fn simplify_paths(states: Vec<MacroState>, tolerance: f64) -> Vec<MacroState> {
+    let mut simplified = Vec::with_capacity(states.len());
+    let mut run: Vec<MacroState> = Vec::new();
+
+    for state in states {
+        let continues_run = run
+            .first()
+            .is_none_or(|first| first.keys_pressed == state.keys_pressed);
+        if !is_plain_move(&state) || !continues_run {
+            simplify_run(std::mem::take(&mut run), tolerance, &mut simplified);
+        }
+
+        if is_plain_move(&state) {
+            run.push(state);
+        } else {
+            simplified.push(state);
+        }
+    }
+    simplify_run(run, tolerance, &mut simplified);
+
+    simplified
+}
+
+/// Simplify one run of moves into `output`
+fn simplify_run(run: Vec<MacroState>, tolerance: f64, output: &mut Vec<MacroState>) {
+    if run.is_empty() {
+        return;
+    }
+
+    // Pointer position before each move, and after the last one
+    let mut points = vec![(0i64, 0i64)];
+    for state in &run {
+        let (x, y) = points[points.len() - 1];
+        points.push((x + state.mouse_delta.0 as i64, y + state.mouse_delta.1 as i64));
+    }
+    let keep = rdp_keep(&points, tolerance);
+
+    // A kept point starts a new move that covers every move up to the next one
+    for (index, state) in run.into_iter().enumerate() {
+        match output.last_mut() {
+            Some(merged) if !keep[index] => {
+                merged.duration_us += state.duration_us;
+                merged.mouse_delta.0 += state.mouse_delta.0;
+                merged.mouse_delta.1 += state.mouse_delta.1;
+            }
+            _ => output.push(state),
+        }
+    }
+}
+
+/// Mark the points of a path to keep, so that every dropped point is within
+/// `tolerance` of the simplified path (Ramer-Douglas-Peucker)
+///
+/// The first and last points are always kept.
+fn rdp_keep(points: &[(i64, i64)], tolerance: f64) -> Vec<bool> {
+    let last = points.len() - 1;
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[last] = true;
+
+    // Explicit stack instead of recursion, as long drags have many points
+    let mut segments = vec![(0, last)];
+    while let Some((start, end)) = segments.pop() {
+        let farthest = (start + 1..end)
+            .map(|index| (index, distance_to_segment(points[index], points[start], points[end])))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        if let Some((index, distance)) = farthest {
+            if distance > tolerance {
+                keep[index] = true;
+                segments.push((start, index));
+                segments.push((index, end));
+            }
+        }
+    }
+
+    keep
+}
+
+/// Distance from `point` to the line segment between `start` and `end`
+fn distance_to_segment(point: (i64, i64), start: (i64, i64), end: (i64, i64)) -> f64 {
+    let (px, py) = (point.0 as f64, point.1 as f64);
+    let (sx, sy) = (start.0 as f64, start.1 as f64);
+    let (dx, dy) = (end.0 as f64 - sx, end.1 as f64 - sy);
+
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared == 0.0 {
+        0.0
+    } else {
+        (((px - sx) * dx + (py - sy) * dy) / length_squared).clamp(0.0, 1.0)
+    };
+
+    (px - sx - t * dx).hypot(py - sy - t * dy)
+}
+
+/// Round state boundaries to multiples of `step_us`, e.g. to whole
+/// milliseconds for a tidier macro file
+///
+/// Boundaries are rounded rather than each duration, so rounding errors don't
+/// add up over a long macro. A state that rounds to nothing keeps its actions:
+/// keys held for less than a step become a tap. A wait that rounds to nothing
+/// between two presses of the same keys lasts a step instead, so a fast
+/// double-tap doesn't become one long press.
+pub fn quantize(states: Vec<MacroState>, step_us: u64) -> Vec<MacroState> {
+    if step_us <= 1 {
+        return states;
+    }
+
+    let mut quantized: Vec<MacroState> = Vec::with_capacity(states.len());
+    let mut elapsed_us = 0u64;
+    let mut rounded_us = 0u64;
+
+    for index in 0..states.len() {
+        let mut state = states[index].clone();
+        elapsed_us += state.duration_us;
+        let end_us = (elapsed_us + step_us / 2) / step_us * step_us;
+        // Behind by a step after a kept wait, until the boundaries catch up
+        state.duration_us = end_us.saturating_sub(rounded_us);
+
+        if state.is_empty() && state.duration_us == 0 {
+            let separates_presses = quantized.last().zip(states.get(index + 1)).is_some_and(
+                |(before, after)| {
+                    !before.keys_pressed.is_empty() && before.keys_pressed == after.keys_pressed
+                },
+            );
+            if !separates_presses {
+                // A wait that rounds away has nothing left to do
+                continue;
+            }
+            state.duration_us = step_us;
+        }
+
+        rounded_us += state.duration_us;
+        quantized.push(state);
+    }
+
+    merge_consecutive_states(quantized)
+}
+
 /// Merge consecutive states that have the same keys pressed
 fn merge_consecutive_states(states: Vec<MacroState>) -> Vec<MacroState> {
     if states.is_empty() {
@@ -141,15 +349,17 @@ fn merge_consecutive_states(states: Vec<MacroState>) -> Vec<MacroState> {
     let mut current = states[0].clone();
 
     for state in states.into_iter().skip(1) {
-        // Only merge if keys match and no mouse/scroll movement in either
+        // Only merge if keys match and no mouse/scroll/absolute movement in either
         // (small movements already filtered to (0, 0) before merging)
         if current.keys_pressed == state.keys_pressed
             && current.mouse_delta == (0, 0)
             && state.mouse_delta == (0, 0)
             && current.scroll_delta == (0, 0)
             && state.scroll_delta == (0, 0)
+            && current.abs_changes.is_empty()
+            && state.abs_changes.is_empty()
         {
-            current.duration_ms += state.duration_ms;
+            current.duration_us += state.duration_us;
         } else {
             merged.push(current);
             current = state;
@@ -160,42 +370,66 @@ fn merge_consecutive_states(states: Vec<MacroState>) -> Vec<MacroState> {
     merged
 }
 
+/// Order keys pressed at the same moment: modifiers first, otherwise as given
+fn press_order(keys: &mut [u16]) {
+    keys.sort_by_key(|&code| !keymap::is_modifier(code));
+}
+
+/// Order keys released at the same moment: last pressed first, modifiers last
+fn release_order(keys: &mut [u16]) {
+    keys.reverse();
+    keys.sort_by_key(|&code| keymap::is_modifier(code));
+}
+
 /// Convert state-based representation back to events
+///
+/// Keys go down in the order they were pressed and come up in reverse, so a
+/// chord like CTRL+C is replayed as CTRL, C, release C, release CTRL.
 pub fn states_to_events(states: &[MacroState]) -> Vec<RecordedEvent> {
     let mut events = Vec::new();
     let mut timestamp_us = 0u64;
-    let mut current_keys: HashSet<u16> = HashSet::new();
+    let mut current_keys: Vec<u16> = Vec::new();
 
     for state in states {
         // Determine which keys need to be pressed and released
-        let keys_to_press: Vec<u16> = state
+        let mut keys_to_press: Vec<u16> = state
             .keys_pressed
-            .difference(&current_keys)
+            .iter()
+            .filter(|code| !current_keys.contains(code))
             .copied()
             .collect();
-        let keys_to_release: Vec<u16> = current_keys
-            .difference(&state.keys_pressed)
+        press_order(&mut keys_to_press);
+        let mut keys_to_release: Vec<u16> = current_keys
+            .iter()
+            .filter(|code| !state.keys_pressed.contains(code))
             .copied()
             .collect();
+        release_order(&mut keys_to_release);
 
-        // Release keys that are no longer pressed
-        for key_code in keys_to_release {
+        // Absolute axis changes come first and share one frame with the key
+        // changes, so a touch or pen lands at its new position (and slot)
+        // together with its BTN_TOUCH, as the device reported it
+        let frame_start = events.len();
+        for &(axis_code, value) in &state.abs_changes {
             events.push(RecordedEvent {
                 timestamp_us,
-                event: InputEvent::new(EventType::KEY.0, key_code, 0),
-            });
-            events.push(RecordedEvent {
-                timestamp_us,
-                event: InputEvent::new(EventType::SYNCHRONIZATION.0, 0, 0),
+                event: InputEvent::new(EventType::ABSOLUTE.0, axis_code, value),
             });
         }
 
-        // Press new keys
-        for key_code in &keys_to_press {
+        // Release keys that are no longer pressed, then press new keys, all
+        // in the same frame so a chord arrives as one update
+        let key_changes = keys_to_release
+            .iter()
+            .map(|&code| (code, 0))
+            .chain(keys_to_press.iter().map(|&code| (code, 1)));
+        for (key_code, value) in key_changes {
             events.push(RecordedEvent {
                 timestamp_us,
-                event: InputEvent::new(EventType::KEY.0, *key_code, 1),
+                event: InputEvent::new(EventType::KEY.0, key_code, value),
             });
+        }
+        if events.len() > frame_start {
             events.push(RecordedEvent {
                 timestamp_us,
                 event: InputEvent::new(EventType::SYNCHRONIZATION.0, 0, 0),
@@ -246,15 +480,18 @@ pub fn states_to_events(states: &[MacroState]) -> Vec<RecordedEvent> {
         current_keys = state.keys_pressed.clone();
 
         // Advance time
-        timestamp_us += state.duration_ms * 1000; // Convert ms to microseconds
+        timestamp_us += state.duration_us;
     }
 
-    // Release all remaining keys at the end
-    for key_code in current_keys {
+    // Release all remaining keys at the end, together
+    release_order(&mut current_keys);
+    for &key_code in &current_keys {
         events.push(RecordedEvent {
             timestamp_us,
             event: InputEvent::new(EventType::KEY.0, key_code, 0),
         });
+    }
+    if !current_keys.is_empty() {
         events.push(RecordedEvent {
             timestamp_us,
             event: InputEvent::new(EventType::SYNCHRONIZATION.0, 0, 0),
@@ -270,7 +507,7 @@ mod tests {
 
     #[test]
     fn test_empty_events() {
-        let states = events_to_states(&[]);
+        let states = events_to_states(&[], &MouseFilter::default());
         assert!(states.is_empty());
     }
 
@@ -287,32 +524,215 @@ mod tests {
             },
         ];
 
-        let states = events_to_states(&events);
+        let states = events_to_states(&events, &MouseFilter::default());
         assert_eq!(states.len(), 1);
-        assert_eq!(states[0].duration_ms, 100);
+        assert_eq!(states[0].duration_us, 100_000);
         assert!(states[0].keys_pressed.contains(&17));
     }
 
+    #[test]
+    fn test_chord_order_preserved() {
+        let key = |timestamp_us, code, value| RecordedEvent {
+            timestamp_us,
+            event: InputEvent::new(EventType::KEY.0, code, value),
+        };
+        let events = vec![
+            key(0, 29, 1),      // CTRL press
+            key(20_000, 46, 1), // C press
+            key(80_000, 46, 0), // C release
+            key(90_000, 29, 0), // CTRL release
+        ];
+
+        let states = events_to_states(&events, &MouseFilter::default());
+        assert_eq!(states[1].keys_pressed, vec![29, 46]);
+
+        // Everything at once, as a "tap C+CTRL" would be
+        let chord = MacroState {
+            keys_pressed: vec![46, 29],
+            ..MacroState::new(50_000)
+        };
+
+        for states in [states, vec![chord.clone()]] {
+            let keys: Vec<(u16, i32)> = states_to_events(&states)
+                .iter()
+                .filter(|e| e.event.event_type() == EventType::KEY)
+                .map(|e| (e.event.code(), e.event.value()))
+                .collect();
+            assert_eq!(keys, vec![(29, 1), (46, 1), (46, 0), (29, 0)]);
+        }
+
+        // Both presses share a frame, and so do both releases
+        let types: Vec<EventType> = states_to_events(&[chord])
+            .iter()
+            .map(|e| e.event.event_type())
+            .collect();
+        let (key, syn) = (EventType::KEY, EventType::SYNCHRONIZATION);
+        assert_eq!(types, vec![key, key, syn, key, key, syn]);
+    }
+
+    /// Total mouse movement of some states
+    fn total_movement(states: &[MacroState]) -> (i32, i32) {
+        states.iter().fold((0, 0), |(x, y), state| {
+            (x + state.mouse_delta.0, y + state.mouse_delta.1)
+        })
+    }
+
+    #[test]
+    fn test_small_moves_are_carried() {
+        let mut events = Vec::new();
+        for i in 0..10 {
+            events.push(RecordedEvent {
+                timestamp_us: i * 10_000,
+                event: InputEvent::new(EventType::RELATIVE.0, 0, 2), // REL_X
+            });
+        }
+        events.push(RecordedEvent {
+            timestamp_us: 100_000,
+            event: InputEvent::new(EventType::KEY.0, 272, 1), // BTN_LEFT press
+        });
+
+        let states = events_to_states(&events, &MouseFilter::default());
+        let moves: Vec<(i32, i32)> = states
+            .iter()
+            .map(|state| state.mouse_delta)
+            .filter(|&delta| delta != (0, 0))
+            .collect();
+        // 2px steps add up to moves of 6px, and the rest moves before the click
+        assert_eq!(moves, vec![(6, 0), (6, 0), (6, 0), (2, 0)]);
+        assert_eq!(total_movement(&states), (20, 0));
+
+        let strict = MouseFilter {
+            threshold: 50,
+            simplify: None,
+        };
+        let states = events_to_states(&events, &strict);
+        assert_eq!(total_movement(&states), (20, 0));
+    }
+
+    #[test]
+    fn test_simplify_paths() {
+        // Drag right 40px then down 40px in 4px steps, holding BTN_LEFT
+        let mut states = Vec::new();
+        for step in 0..20 {
+            let mut state = MacroState::new(10_000);
+            state.keys_pressed = vec![272];
+            state.mouse_delta = if step < 10 { (4, 0) } else { (0, 4) };
+            states.push(state);
+        }
+        let mut release = MacroState::new(10_000);
+        release.mouse_delta = (3, 3);
+        states.push(release);
+
+        let simplified = simplify_paths(states.clone(), 1.0);
+        let moves: Vec<((i32, i32), u64)> = simplified
+            .iter()
+            .map(|state| (state.mouse_delta, state.duration_us))
+            .collect();
+        // The corner stays, the move after releasing the button is separate
+        assert_eq!(moves, vec![((40, 0), 100_000), ((0, 40), 100_000), ((3, 3), 10_000)]);
+
+        // A large tolerance cuts the corner, but still ends at the same spot
+        let simplified = simplify_paths(states, 100.0);
+        assert_eq!(simplified.len(), 2);
+        assert_eq!(simplified[0].mouse_delta, (40, 40));
+        assert_eq!(simplified[0].duration_us, 200_000);
+    }
+
+    #[test]
+    fn test_sub_millisecond_timing() {
+        // A click released after 400us, then a second one 700us later
+        let key = |timestamp_us, value| RecordedEvent {
+            timestamp_us,
+            event: InputEvent::new(EventType::KEY.0, 272, value), // BTN_LEFT
+        };
+        let events = vec![key(0, 1), key(400, 0), key(1_100, 1), key(1_500, 0)];
+
+        let states = events_to_states(&events, &MouseFilter::default());
+        let durations: Vec<(u64, bool)> = states
+            .iter()
+            .map(|state| (state.duration_us, state.keys_pressed.is_empty()))
+            .collect();
+        assert_eq!(durations, vec![(400, false), (700, true), (400, false)]);
+
+        let replayed: Vec<(u64, i32)> = states_to_events(&states)
+            .iter()
+            .filter(|e| e.event.event_type() == EventType::KEY)
+            .map(|e| (e.timestamp_us, e.event.value()))
+            .collect();
+        assert_eq!(replayed, vec![(0, 1), (400, 0), (1_100, 1), (1_500, 0)]);
+    }
+
+    #[test]
+    fn test_quantize() {
+        let state = |duration_us, keys: &[u16]| MacroState {
+            keys_pressed: keys.to_vec(),
+            ..MacroState::new(duration_us)
+        };
+        let states = vec![
+            state(400, &[272]),
+            state(700, &[]),
+            state(400, &[272]),
+            state(2_600, &[]),
+            state(10_400, &[17]),
+        ];
+
+        let durations: Vec<u64> = quantize(states.clone(), 1_000)
+            .iter()
+            .map(|state| state.duration_us)
+            .collect();
+        // Boundaries at 0.4, 1.1, 1.5, 4.1 and 14.5ms round to 0, 1, 2, 4, 15
+        assert_eq!(durations, vec![0, 1_000, 1_000, 2_000, 11_000]);
+
+        // The first click survives as a tap
+        assert_eq!(quantize(states.clone(), 1_000)[0].keys_pressed, vec![272]);
+        assert_eq!(quantize(states.clone(), 1), states);
+    }
+
+    #[test]
+    fn test_quantize_keeps_double_tap() {
+        // A tapped twice, 0.4ms apart
+        let key = |timestamp_us, value| RecordedEvent {
+            timestamp_us,
+            event: InputEvent::new(EventType::KEY.0, 30, value),
+        };
+        let events = vec![key(0, 1), key(30_000, 0), key(30_400, 1), key(60_000, 0)];
+
+        let states = quantize(events_to_states(&events, &MouseFilter::default()), 1_000);
+        let durations: Vec<(u64, bool)> = states
+            .iter()
+            .map(|state| (state.duration_us, state.keys_pressed.is_empty()))
+            .collect();
+        assert_eq!(durations, vec![(30_000, false), (1_000, true), (29_000, false)]);
+
+        let presses = states_to_events(&states)
+            .iter()
+            .filter(|e| e.event.event_type() == EventType::KEY && e.event.value() == 1)
+            .count();
+        assert_eq!(presses, 2);
+    }
+
     #[test]
     fn test_merge_consecutive_states() {
         let states = vec![
             MacroState {
-                duration_ms: 10,
-                keys_pressed: [17].iter().copied().collect(),
+                duration_us: 10,
+                keys_pressed: vec![17],
                 mouse_delta: (0, 0),
                 scroll_delta: (0, 0),
+                abs_changes: Vec::new(),
             },
             MacroState {
-                duration_ms: 20,
-                keys_pressed: [17].iter().copied().collect(),
+                duration_us: 20,
+                keys_pressed: vec![17],
                 mouse_delta: (0, 0),
                 scroll_delta: (0, 0),
+                abs_changes: Vec::new(),
             },
         ];
 
         let merged = merge_consecutive_states(states);
         assert_eq!(merged.len(), 1);
-        assert_eq!(merged[0].duration_ms, 30);
+        assert_eq!(merged[0].duration_us, 30);
     }
 
     #[test]
@@ -337,7 +757,7 @@ mod tests {
             },
         ];
 
-        let states = events_to_states(&events);
+        let states = events_to_states(&events, &MouseFilter::default());
 
         // Should have 3 states:
         // 1. W held for 100ms
@@ -346,15 +766,80 @@ mod tests {
         assert_eq!(states.len(), 3);
 
         // First state: W held
-        assert_eq!(states[0].duration_ms, 100);
+        assert_eq!(states[0].duration_us, 100_000);
         assert!(states[0].keys_pressed.contains(&17));
 
         // Second state: Wait (no keys)
-        assert_eq!(states[1].duration_ms, 6000);
+        assert_eq!(states[1].duration_us, 6_000_000);
         assert!(states[1].keys_pressed.is_empty());
 
         // Third state: A held
-        assert_eq!(states[2].duration_ms, 100);
+        assert_eq!(states[2].duration_us, 100_000);
         assert!(states[2].keys_pressed.contains(&30));
     }
+
+    #[test]
+    fn test_absolute_axes() {
+        // Pen down at (100, 200), drag to (150, 250), lift
+        let events = vec![
+            RecordedEvent {
+                timestamp_us: 0,
+                event: InputEvent::new(EventType::ABSOLUTE.0, 0, 100), // ABS_X
+            },
+            RecordedEvent {
+                timestamp_us: 0,
+                event: InputEvent::new(EventType::ABSOLUTE.0, 1, 200), // ABS_Y
+            },
+            RecordedEvent {
+                timestamp_us: 0,
+                event: InputEvent::new(EventType::KEY.0, 330, 1), // BTN_TOUCH press
+            },
+            RecordedEvent {
+                timestamp_us: 10_000,
+                event: InputEvent::new(EventType::ABSOLUTE.0, 0, 150),
+            },
+            RecordedEvent {
+                timestamp_us: 10_000,
+                event: InputEvent::new(EventType::ABSOLUTE.0, 1, 250),
+            },
+            RecordedEvent {
+                timestamp_us: 20_000,
+                event: InputEvent::new(EventType::KEY.0, 330, 0), // BTN_TOUCH release
+            },
+        ];
+
+        let states = events_to_states(&events, &MouseFilter::default());
+        assert_eq!(states.len(), 2);
+        assert_eq!(states[0].abs_changes, vec![(0, 100), (1, 200)]);
+        assert!(states[0].keys_pressed.contains(&330));
+        assert_eq!(states[1].abs_changes, vec![(0, 150), (1, 250)]);
+        assert!(states[1].keys_pressed.contains(&330));
+
+        let replayed = states_to_events(&states);
+        let abs_values: Vec<(u64, u16, i32)> = replayed
+            .iter()
+            .filter(|e| e.event.event_type() == EventType::ABSOLUTE)
+            .map(|e| (e.timestamp_us, e.event.code(), e.event.value()))
+            .collect();
+        assert_eq!(
+            abs_values,
+            vec![(0, 0, 100), (0, 1, 200), (10_000, 0, 150), (10_000, 1, 250)]
+        );
+
+        // The pen lands at its position in the same frame as its BTN_TOUCH
+        let first_frame: Vec<(EventType, u16)> = replayed
+            .iter()
+            .take(4)
+            .map(|e| (e.event.event_type(), e.event.code()))
+            .collect();
+        assert_eq!(
+            first_frame,
+            vec![
+                (EventType::ABSOLUTE, 0),
+                (EventType::ABSOLUTE, 1),
+                (EventType::KEY, 330),
+                (EventType::SYNCHRONIZATION, 0)
+            ]
+        );
+    }
 }
@@ -5,40 +5,141 @@
 //!   hold W+A for 4ms
 //!   wait 100ms
 //!   move 10 -5
+//!   hold W for 120ms move 15 3
+//!   hold BTN_TOUCH for 8ms abs X=512 Y=300 PRESSURE=40
+//!
+//! Mouse buttons have commands of their own:
+//!   click left for 87ms
+//!   double-click left for 60ms/58ms gap 120ms
+//!   drag 200 40 with left for 300ms
+//!   mousedown left
+//!   move 10 -5
+//!   mouseup left
+//!
+//! Macros recorded from a tablet, touchscreen, touchpad or game controller
+//! start with a description of that device, so playback can recreate it:
+//!   device tablet 0003:056a:0357:0110 Wacom Intuos Pro M Pen
+//!   axis X 0 32767 0 0 100
+//!   buttons BTN_TOUCH BTN_TOOL_PEN BTN_STYLUS
+//!   properties DIRECT
+//!
+//! Raw macros store every event exactly as recorded, one per line with its
+//! time in microseconds, type, code and value. Playback sends all of them
+//! except the few the virtual devices don't have, such as MSC_TIMESTAMP:
+//!   format raw 1
+//!   0 1 29 1
+//!   0 0 0 0
+//!
+//! A macro can also say how often it plays (see `repeat`):
+//!   repeat 5
+//!   interval 2s
 
+use crate::devices::DeviceKind;
 use crate::keymap;
+use crate::profile::AbsDevice;
 use crate::recorder::RecordedEvent;
-use crate::state::{events_to_states, states_to_events, MacroState};
-use std::collections::HashSet;
+use crate::repeat::Repeat;
+use crate::state::{events_to_states, quantize, states_to_events, MacroState, MouseFilter};
+use evdev::{AbsInfo, InputEvent};
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, Write};
 use std::path::Path;
+use std::time::Duration;
+
+/// Version of the raw format written by `save`
+const RAW_VERSION: u32 = 1;
+
+/// Longest pause between two clicks written as a `double-click` (in
+/// microseconds), about what desktops accept as one
+const DOUBLE_CLICK_GAP_US: u64 = 500_000;
+
+/// How a macro's events are stored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Human-readable states (hold/wait/move...), simplified from the events
+    #[default]
+    Dsl,
+    /// Every event exactly as recorded
+    Raw,
+}
 
-/// Save recorded events as human-readable DSL
-pub fn save<P: AsRef<Path>>(path: P, events: &[RecordedEvent]) -> io::Result<()> {
+/// A macro: the events to play and the devices needed to play them
+#[derive(Debug, Clone, Default)]
+pub struct Macro {
+    pub events: Vec<RecordedEvent>,
+    /// Absolute-axis device the macro was recorded from, if any
+    pub abs_device: Option<AbsDevice>,
+    /// How often the macro plays, as far as the file says
+    pub repeat: Repeat,
+    /// How the events are (to be) stored
+    pub format: Format,
+    /// How mouse movement is simplified when saved as DSL
+    pub mouse_filter: MouseFilter,
+    /// Round DSL timings to multiples of this; zero keeps every microsecond
+    pub quantize: Duration,
+}
+
+/// Save a macro as human-readable DSL, or as raw events
+pub fn save<P: AsRef<Path>>(path: P, recording: &Macro) -> io::Result<()> {
     let mut file = File::create(path)?;
+    write_macro(&mut file, recording)
+}
+
+/// Load macro from DSL or raw format, whichever the file uses
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Macro> {
+    let file = File::open(path)?;
+    read_macro(BufReader::new(file))
+}
 
+/// Write a macro as DSL or raw events
+fn write_macro(file: &mut impl Write, recording: &Macro) -> io::Result<()> {
     writeln!(file, "# EvKey Macro")?;
     writeln!(file, "# Layout: QWERTY")?;
     writeln!(file)?;
 
+    if let Some(device) = &recording.abs_device {
+        write_abs_device(file, device)?;
+        writeln!(file)?;
+    }
+
+    if recording.format == Format::Raw {
+        writeln!(file, "format raw {}", RAW_VERSION)?;
+        writeln!(file, "# TIME_US TYPE CODE VALUE")?;
+        for recorded in &recording.events {
+            let event = recorded.event;
+            writeln!(
+                file,
+                "{} {} {} {}",
+                recorded.timestamp_us,
+                event.event_type().0,
+                event.code(),
+                event.value()
+            )?;
+        }
+        return Ok(());
+    }
+
     // Convert events to states
-    let states = events_to_states(events);
+    let states = events_to_states(&recording.events, &recording.mouse_filter);
+    let states = quantize(states, recording.quantize.as_micros() as u64);
 
-    // Write each state in DSL format
-    for state in &states {
-        let line = format_state(state);
+    // Write the states in DSL format
+    for line in format_states(&states) {
         writeln!(file, "{}", line)?;
     }
 
     Ok(())
 }
 
-/// Load macro from DSL format
-pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<RecordedEvent>> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+/// Read a macro written in DSL or raw events
+fn read_macro(reader: impl BufRead) -> io::Result<Macro> {
     let mut states = Vec::new();
+    let mut raw_events = Vec::new();
+    let mut abs_device = AbsDevice::default();
+    let mut repeat = Repeat::default();
+    let mut format = Format::Dsl;
+    // Buttons held by `mousedown`
+    let mut buttons_down = Vec::new();
 
     for (line_num, line) in reader.lines().enumerate() {
         let line = line?;
@@ -49,59 +150,356 @@ pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<RecordedEvent>> {
             continue;
         }
 
-        match parse_line(line) {
-            Ok(state) => states.push(state),
-            Err(e) => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("Line {}: {}", line_num + 1, e),
-                ));
+        let result = if let Some(version) = line.strip_prefix("format raw ") {
+            parse_raw_version(version, &states).map(|()| format = Format::Raw)
+        } else if let Some(result) = repeat.parse_line(line) {
+            result
+        } else if let Some(result) = parse_device_line(line, &mut abs_device) {
+            result
+        } else if format == Format::Raw {
+            parse_raw_event(line).map(|event| raw_events.push(event))
+        } else {
+            let held = states.last().map_or(&[][..], |state| &state.keys_pressed[..]);
+            parse_statement(line, &mut buttons_down, held).map(|parsed| states.extend(parsed))
+        };
+
+        if let Err(e) = result {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Line {}: {}", line_num + 1, e),
+            ));
+        }
+    }
+
+    let events = match format {
+        Format::Raw => raw_events,
+        // Convert states back to events
+        Format::Dsl => states_to_events(&states),
+    };
+
+    Ok(Macro {
+        events,
+        abs_device: (!abs_device.axes.is_empty()).then_some(abs_device),
+        repeat,
+        format,
+        mouse_filter: MouseFilter::default(),
+        quantize: Duration::ZERO,
+    })
+}
+
+/// Check the version of a `format raw N` line
+fn parse_raw_version(version: &str, states: &[MacroState]) -> Result<(), String> {
+    if !states.is_empty() {
+        return Err("'format' must come before the events".to_string());
+    }
+    match version.trim().parse::<u32>() {
+        Ok(RAW_VERSION) => Ok(()),
+        Ok(version) => Err(format!(
+            "Raw format version {} is not supported (this evkey reads version {})",
+            version, RAW_VERSION
+        )),
+        Err(_) => Err(format!("Invalid raw format version: {}", version)),
+    }
+}
+
+/// Parse a raw event line: "TIME_US TYPE CODE VALUE"
+fn parse_raw_event(line: &str) -> Result<RecordedEvent, String> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let [timestamp_us, event_type, code, value] = parts[..] else {
+        return Err(format!("Raw event must be TIME_US TYPE CODE VALUE: {}", line));
+    };
+    let invalid = |part: &str| format!("Invalid raw event field '{}': {}", part, line);
+
+    Ok(RecordedEvent {
+        timestamp_us: timestamp_us.parse().map_err(|_| invalid(timestamp_us))?,
+        event: InputEvent::new(
+            event_type.parse().map_err(|_| invalid(event_type))?,
+            code.parse().map_err(|_| invalid(code))?,
+            value.parse().map_err(|_| invalid(value))?,
+        ),
+    })
+}
+
+/// Write the description of an absolute-axis device
+fn write_abs_device(file: &mut impl Write, device: &AbsDevice) -> io::Result<()> {
+    writeln!(file, "# Absolute device (axis NAME MIN MAX FUZZ FLAT RESOLUTION)")?;
+
+    if let (Some(kind), Some((bus, vendor, product, version))) = (device.kind, device.id) {
+        writeln!(
+            file,
+            "device {} {:04x}:{:04x}:{:04x}:{:04x} {}",
+            kind,
+            bus,
+            vendor,
+            product,
+            version,
+            device.name.as_deref().unwrap_or("")
+        )?;
+    }
+
+    for (code, info) in &device.axes {
+        writeln!(
+            file,
+            "axis {} {} {} {} {} {}",
+            keymap::abs_axis_to_name(*code),
+            info.minimum(),
+            info.maximum(),
+            info.fuzz(),
+            info.flat(),
+            info.resolution()
+        )?;
+    }
+
+    let buttons: Vec<String> = device
+        .buttons
+        .iter()
+        .map(|&code| keymap::keycode_to_name(code))
+        .collect();
+    if !buttons.is_empty() {
+        writeln!(file, "buttons {}", buttons.join(" "))?;
+    }
+
+    let properties: Vec<&str> = device
+        .properties
+        .iter()
+        .filter_map(|&code| keymap::property_to_name(code))
+        .collect();
+    if !properties.is_empty() {
+        writeln!(file, "properties {}", properties.join(" "))?;
+    }
+
+    Ok(())
+}
+
+/// Parse an absolute device description line into `device`
+/// Returns None if the line isn't part of a device description
+fn parse_device_line(line: &str, device: &mut AbsDevice) -> Option<Result<(), String>> {
+    // Parse "device KIND BUS:VENDOR:PRODUCT:VERSION NAME"
+    if let Some(rest) = line.strip_prefix("device ") {
+        let mut parts = rest.splitn(3, ' ');
+        let (Some(kind), Some(id)) = (parts.next(), parts.next()) else {
+            return Some(Err(format!("Invalid 'device' syntax: {}", line)));
+        };
+
+        let Some(kind) = DeviceKind::from_name(kind) else {
+            return Some(Err(format!("Unknown device kind: {}", kind)));
+        };
+
+        let ids: Vec<u16> = id
+            .split(':')
+            .filter_map(|part| u16::from_str_radix(part, 16).ok())
+            .collect();
+        let [bus, vendor, product, version] = ids[..] else {
+            return Some(Err(format!("Invalid device ID: {}", id)));
+        };
+
+        device.kind = Some(kind);
+        device.id = Some((bus, vendor, product, version));
+        device.name = parts.next().map(|name| name.trim().to_string()).filter(|n| !n.is_empty());
+        return Some(Ok(()));
+    }
+
+    // Parse "axis NAME MIN MAX FUZZ FLAT RESOLUTION"
+    if let Some(rest) = line.strip_prefix("axis ") {
+        let parts: Vec<&str> = rest.split_whitespace().collect();
+        if parts.len() != 6 {
+            return Some(Err(format!("Invalid 'axis' syntax: {}", line)));
+        }
+
+        let Some(code) = keymap::name_to_abs_axis(parts[0]) else {
+            return Some(Err(format!("Unknown axis: {}", parts[0])));
+        };
+
+        let mut values = [0i32; 5];
+        for (value, part) in values.iter_mut().zip(&parts[1..]) {
+            match part.parse() {
+                Ok(v) => *value = v,
+                Err(_) => return Some(Err(format!("Invalid axis value: {}", part))),
+            }
+        }
+        let [min, max, fuzz, flat, resolution] = values;
+
+        device
+            .axes
+            .push((code, AbsInfo::new(0, min, max, fuzz, flat, resolution)));
+        return Some(Ok(()));
+    }
+
+    // Parse "buttons BTN_TOUCH BTN_TOOL_PEN ..."
+    if let Some(rest) = line.strip_prefix("buttons ") {
+        for name in rest.split_whitespace() {
+            match keymap::name_to_keycode(name) {
+                Some(code) => device.buttons.push(code),
+                None => return Some(Err(format!("Unknown button: {}", name))),
+            }
+        }
+        return Some(Ok(()));
+    }
+
+    // Parse "properties DIRECT ..."
+    if let Some(rest) = line.strip_prefix("properties ") {
+        for name in rest.split_whitespace() {
+            match keymap::name_to_property(name) {
+                Some(code) => device.properties.push(code),
+                None => return Some(Err(format!("Unknown property: {}", name))),
+            }
+        }
+        return Some(Ok(()));
+    }
+
+    None
+}
+
+/// Format states as DSL lines
+///
+/// A mouse button held over several states gets a `mousedown` and `mouseup`
+/// around them, and a click, a short pause and the same click again become
+/// a `double-click`. Everything else is one `format_state` each.
+fn format_states(states: &[MacroState]) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut buttons_down: Vec<u16> = Vec::new();
+    let mut index = 0;
+
+    while index < states.len() {
+        let state = &states[index];
+
+        for &button in buttons_down.iter().rev() {
+            if !state.keys_pressed.contains(&button) {
+                lines.push(format!("mouseup {}", button_name(button)));
+            }
+        }
+        buttons_down.retain(|button| state.keys_pressed.contains(button));
+
+        let next = states.get(index + 1);
+        for &code in &state.keys_pressed {
+            let held_on = next.is_some_and(|next| next.keys_pressed.contains(&code));
+            if held_on && !buttons_down.contains(&code) && keymap::mouse_button_to_name(code).is_some() {
+                lines.push(format!("mousedown {}", button_name(code)));
+                buttons_down.push(code);
             }
         }
+
+        if buttons_down.is_empty() {
+            if let Some(line) = format_double_click(&states[index..]) {
+                lines.push(line);
+                index += 3;
+                continue;
+            }
+        }
+
+        let mut state = state.clone();
+        state.keys_pressed.retain(|code| !buttons_down.contains(code));
+        lines.push(format_state(&state));
+        index += 1;
     }
 
-    // Convert states back to events
-    Ok(states_to_events(&states))
+    for &button in buttons_down.iter().rev() {
+        lines.push(format!("mouseup {}", button_name(button)));
+    }
+
+    lines
+}
+
+/// DSL name of a button that has one
+fn button_name(code: u16) -> &'static str {
+    keymap::mouse_button_to_name(code).unwrap_or_default()
+}
+
+/// Format the first three states as a `double-click`, if they are one: the
+/// same button clicked twice with a short pause and nothing else in between
+///
+/// The clicks may be held for different times, as they are when recorded.
+fn format_double_click(states: &[MacroState]) -> Option<String> {
+    let [first, pause, second, ..] = states else {
+        return None;
+    };
+    let [button] = first.keys_pressed[..] else {
+        return None;
+    };
+    let name = keymap::mouse_button_to_name(button)?;
+
+    let is_click = |state: &MacroState| {
+        state.keys_pressed == [button]
+            && state.mouse_delta == (0, 0)
+            && state.scroll_delta == (0, 0)
+            && state.abs_changes.is_empty()
+    };
+    let released_after = states
+        .get(3)
+        .is_none_or(|next| !next.keys_pressed.contains(&button));
+
+    let matches = is_click(first)
+        && is_click(second)
+        && pause.is_empty()
+        && (1..=DOUBLE_CLICK_GAP_US).contains(&pause.duration_us)
+        && released_after;
+    if !matches {
+        return None;
+    }
+
+    let mut line = format!("double-click {}", name);
+    if first.duration_us != second.duration_us {
+        line += &format!(
+            " for {}/{}",
+            format_duration(first.duration_us),
+            format_duration(second.duration_us)
+        );
+    } else if first.duration_us > 0 {
+        line += &format!(" for {}", format_duration(first.duration_us));
+    }
+    line += &format!(" gap {}", format_duration(pause.duration_us));
+    Some(line)
 }
 
 /// Format a MacroState as a DSL line
 fn format_state(state: &MacroState) -> String {
     // Handle empty state (just waiting)
-    if state.keys_pressed.is_empty()
-        && state.mouse_delta == (0, 0)
-        && state.scroll_delta == (0, 0)
-    {
-        if state.duration_ms > 0 {
-            return format!("wait {}ms", state.duration_ms);
+    if state.is_empty() {
+        if state.duration_us > 0 {
+            return format!("wait {}", format_duration(state.duration_us));
         } else {
             return "# empty state".to_string();
         }
     }
 
     let mut parts = Vec::new();
+    let mut mouse_delta = state.mouse_delta;
 
-    // Format keys
-    if !state.keys_pressed.is_empty() {
-        let mut keys: Vec<String> = state
+    // A mouse button on its own is a click, or a drag if the mouse moves
+    let button = match state.keys_pressed[..] {
+        [code] => keymap::mouse_button_to_name(code),
+        _ => None,
+    };
+    if let Some(name) = button {
+        let mut command = if mouse_delta == (0, 0) {
+            format!("click {}", name)
+        } else {
+            format!("drag {} {} with {}", mouse_delta.0, mouse_delta.1, name)
+        };
+        if state.duration_us > 0 {
+            command += &format!(" for {}", format_duration(state.duration_us));
+        }
+        parts.push(command);
+        mouse_delta = (0, 0);
+    } else if !state.keys_pressed.is_empty() {
+        // In the order they were pressed
+        let keys: Vec<String> = state
             .keys_pressed
             .iter()
-            .filter_map(|&code| keymap::keycode_to_name(code))
+            .map(|&code| keymap::keycode_to_name(code))
             .collect();
-        keys.sort(); // Consistent ordering
 
-        if state.duration_ms > 0 {
-            parts.push(format!("hold {} for {}ms", keys.join("+"), state.duration_ms));
+        if state.duration_us > 0 {
+            let duration = format_duration(state.duration_us);
+            parts.push(format!("hold {} for {}", keys.join("+"), duration));
         } else {
             parts.push(format!("tap {}", keys.join("+")));
         }
     }
 
     // Format mouse movement
-    if state.mouse_delta != (0, 0) {
-        parts.push(format!(
-            "move {} {}",
-            state.mouse_delta.0, state.mouse_delta.1
-        ));
+    if mouse_delta != (0, 0) {
+        parts.push(format!("move {} {}", mouse_delta.0, mouse_delta.1));
     }
 
     // Format scroll
@@ -128,156 +526,359 @@ fn format_state(state: &MacroState) -> String {
         }
     }
 
+    // Format absolute axes
+    if !state.abs_changes.is_empty() {
+        let changes: Vec<String> = state
+            .abs_changes
+            .iter()
+            .map(|&(code, value)| format!("{}={}", keymap::abs_axis_to_name(code), value))
+            .collect();
+        parts.push(format!("abs {}", changes.join(" ")));
+    }
+
     // If we have duration but no keys (only mouse/scroll actions), add wait after
     let result = parts.join(" ");
     if result.is_empty() {
         // No actions - just a wait or empty state
-        if state.duration_ms > 0 {
-            format!("wait {}ms", state.duration_ms)
+        if state.duration_us > 0 {
+            format!("wait {}", format_duration(state.duration_us))
         } else {
             "# empty state".to_string()
         }
-    } else if state.duration_ms > 0 && state.keys_pressed.is_empty() {
+    } else if state.duration_us > 0 && state.keys_pressed.is_empty() {
         // Has actions (mouse/scroll) with duration
-        format!("{}\nwait {}ms", result, state.duration_ms)
+        format!("{}\nwait {}", result, format_duration(state.duration_us))
     } else {
         result
     }
 }
 
 /// Parse a DSL line into a MacroState
+///
+/// A line is a sequence of clauses that together describe one state:
+///   hold KEYS for DURATION    keys held for the duration of the state
+///   tap KEYS                  keys pressed and released at once
+///   wait DURATION             nothing held for the duration
+///   move X Y                  relative mouse movement
+///   scroll DIRECTION AMOUNT   wheel movement (may appear twice, vertical and
+///                             horizontal)
+///   abs NAME=VALUE ...        absolute axis values (always last)
+///   click BUTTON [for DURATION]
+///                             a mouse button held for the duration, or
+///                             pressed and released at once
+///   drag X Y with BUTTON [for DURATION]
+///                             mouse movement with a button held
+///
+/// e.g. `hold W for 120ms move 15 3` holds W while moving the mouse.
 fn parse_line(line: &str) -> Result<MacroState, String> {
-    let line = line.trim();
+    let mut state = MacroState::new(0);
+    let mut tokens = line.split_whitespace().peekable();
+    let mut seen = Vec::new();
 
-    // Parse "hold KEY for NNms" or "hold KEY+KEY2 for NNms"
-    if let Some(rest) = line.strip_prefix("hold ") {
-        let parts: Vec<&str> = rest.split(" for ").collect();
-        if parts.len() != 2 {
-            return Err(format!("Invalid 'hold' syntax: {}", line));
-        }
+    while let Some(clause) = tokens.next() {
+        let once = |seen: &mut Vec<&'static str>, name: &'static str| {
+            if seen.contains(&name) {
+                Err(format!("'{}' given twice: {}", name, line))
+            } else {
+                seen.push(name);
+                Ok(())
+            }
+        };
 
-        let keys_str = parts[0];
-        let duration_str = parts[1];
+        match clause {
+            "hold" => {
+                once(&mut seen, "keys")?;
+                once(&mut seen, "duration")?;
+                // Allow spaces around '+' in the keys
+                let mut keys = String::new();
+                for token in tokens.by_ref() {
+                    if token == "for" {
+                        break;
+                    }
+                    keys.push_str(token);
+                }
+                let duration = tokens
+                    .next()
+                    .ok_or_else(|| format!("Invalid 'hold' syntax: {}", line))?;
+                state.keys_pressed = parse_keys(&keys)?;
+                state.duration_us = parse_duration(duration)?.as_micros() as u64;
+            }
+            "tap" => {
+                once(&mut seen, "keys")?;
+                let mut keys = String::new();
+                while let Some(token) = tokens.next_if(|token| !is_clause(token)) {
+                    keys.push_str(token);
+                }
+                state.keys_pressed = parse_keys(&keys)?;
+            }
+            "wait" => {
+                once(&mut seen, "duration")?;
+                let duration = tokens
+                    .next()
+                    .ok_or_else(|| format!("Invalid 'wait' syntax: {}", line))?;
+                state.duration_us = parse_duration(duration)?.as_micros() as u64;
+            }
+            "move" => {
+                once(&mut seen, "move")?;
+                let (Some(x), Some(y)) = (tokens.next(), tokens.next()) else {
+                    return Err(format!("Invalid 'move' syntax: {}", line));
+                };
+                let x: i32 = x.parse().map_err(|_| format!("Invalid X coordinate: {}", x))?;
+                let y: i32 = y.parse().map_err(|_| format!("Invalid Y coordinate: {}", y))?;
+                state.mouse_delta = (x, y);
+            }
+            // e.g. "scroll up 3" or "scroll down 5"
+            "scroll" => {
+                let (Some(direction), Some(amount)) = (tokens.next(), tokens.next()) else {
+                    return Err(format!("Invalid 'scroll' syntax: {}", line));
+                };
+                let amount: i32 = amount
+                    .parse()
+                    .map_err(|_| format!("Invalid scroll amount: {}", amount))?;
 
-        // Parse duration
-        let duration_ms = parse_duration(duration_str)?;
+                match direction {
+                    "up" | "down" => once(&mut seen, "vertical scroll")?,
+                    _ => once(&mut seen, "horizontal scroll")?,
+                }
+                match direction {
+                    "up" => state.scroll_delta.0 = amount,
+                    "down" => state.scroll_delta.0 = -amount,
+                    "left" => state.scroll_delta.1 = -amount,
+                    "right" => state.scroll_delta.1 = amount,
+                    _ => {
+                        return Err(format!(
+                            "Invalid scroll direction '{}', use up/down/left/right",
+                            direction
+                        ))
+                    }
+                }
+            }
+            "abs" => {
+                let rest: Vec<&str> = tokens.by_ref().collect();
+                state.abs_changes = parse_abs_changes(&rest.join(" "))?;
+            }
+            "click" | "drag" => {
+                once(&mut seen, "keys")?;
+                if clause == "drag" {
+                    once(&mut seen, "move")?;
+                    let (Some(x), Some(y), Some("with")) = (tokens.next(), tokens.next(), tokens.next())
+                    else {
+                        return Err(format!("Invalid 'drag' syntax: {}", line));
+                    };
+                    let x: i32 = x.parse().map_err(|_| format!("Invalid X coordinate: {}", x))?;
+                    let y: i32 = y.parse().map_err(|_| format!("Invalid Y coordinate: {}", y))?;
+                    state.mouse_delta = (x, y);
+                }
 
-        // Parse keys
-        let keys = parse_keys(keys_str)?;
+                let button = tokens
+                    .next()
+                    .ok_or_else(|| format!("Invalid '{}' syntax: {}", clause, line))?;
+                state.keys_pressed = vec![parse_button(button)?];
 
-        return Ok(MacroState {
-            duration_ms,
-            keys_pressed: keys,
-            mouse_delta: (0, 0),
-            scroll_delta: (0, 0),
-        });
+                if tokens.next_if_eq(&"for").is_some() {
+                    once(&mut seen, "duration")?;
+                    let duration = tokens
+                        .next()
+                        .ok_or_else(|| format!("Invalid '{}' syntax: {}", clause, line))?;
+                    state.duration_us = parse_duration(duration)?.as_micros() as u64;
+                }
+            }
+            _ => return Err(format!("Unknown command: {}", line)),
+        }
     }
 
-    // Parse "wait NNms"
-    if let Some(rest) = line.strip_prefix("wait ") {
-        let duration_ms = parse_duration(rest)?;
-        return Ok(MacroState {
-            duration_ms,
-            keys_pressed: HashSet::new(),
-            mouse_delta: (0, 0),
-            scroll_delta: (0, 0),
-        });
-    }
+    Ok(state)
+}
 
-    // Parse "move X Y"
-    if let Some(rest) = line.strip_prefix("move ") {
-        let parts: Vec<&str> = rest.split_whitespace().collect();
-        if parts.len() != 2 {
-            return Err(format!("Invalid 'move' syntax: {}", line));
-        }
+/// Check if a word starts a clause of a DSL line
+fn is_clause(word: &str) -> bool {
+    matches!(
+        word,
+        "hold" | "tap" | "wait" | "move" | "scroll" | "abs" | "click" | "drag"
+    )
+}
 
-        let x: i32 = parts[0]
-            .parse()
-            .map_err(|_| format!("Invalid X coordinate: {}", parts[0]))?;
-        let y: i32 = parts[1]
-            .parse()
-            .map_err(|_| format!("Invalid Y coordinate: {}", parts[1]))?;
+/// Parse a DSL line into the states it stands for
+///
+/// Most lines are one state (see `parse_line`), but these span several:
+///   double-click BUTTON [for DURATION[/DURATION]] [gap DURATION]
+///                             two clicks, held for the duration (or the
+///                             first and second one each for its own), with
+///                             a pause between them (default 100ms)
+///   mousedown BUTTON          press the button and keep it held on the
+///                             following lines...
+///   mouseup BUTTON            ...until it's released here
+///
+/// `buttons_down` tracks the buttons held by `mousedown`. `held` are the
+/// keys of the state before, which stay down over a `mousedown` or `mouseup`
+/// (as SHIFT does in a shift-click), except for a button still down at the
+/// end of a `click` or `drag`, which a `mousedown` of it releases first.
+fn parse_statement(
+    line: &str,
+    buttons_down: &mut Vec<u16>,
+    held: &[u16],
+) -> Result<Vec<MacroState>, String> {
+    let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let rest = rest.trim();
 
-        return Ok(MacroState {
-            duration_ms: 0,
-            keys_pressed: HashSet::new(),
-            mouse_delta: (x, y),
-            scroll_delta: (0, 0),
-        });
-    }
+    // The state before with only the button changed
+    let unchanged = |buttons_down: &[u16]| MacroState {
+        keys_pressed: held
+            .iter()
+            .filter(|code| !buttons_down.contains(code))
+            .copied()
+            .collect(),
+        ..MacroState::new(0)
+    };
 
-    // Parse "scroll DIRECTION AMOUNT" (e.g., "scroll up 3" or "scroll down 5")
-    if let Some(rest) = line.strip_prefix("scroll ") {
-        let parts: Vec<&str> = rest.split_whitespace().collect();
-        if parts.len() != 2 {
-            return Err(format!("Invalid 'scroll' syntax: {}", line));
+    // The state releasing a button pressed again by `mousedown`
+    let mut release = None;
+
+    let mut states = match command {
+        "double-click" => parse_double_click(rest, line)?,
+        "mousedown" => {
+            let button = parse_button(rest)?;
+            let state = unchanged(buttons_down);
+            if !buttons_down.contains(&button) {
+                if held.contains(&button) {
+                    let mut keys = buttons_down.clone();
+                    keys.extend(state.keys_pressed.iter().filter(|&&code| code != button));
+                    release = Some(MacroState {
+                        keys_pressed: keys,
+                        ..MacroState::new(0)
+                    });
+                }
+                buttons_down.push(button);
+            }
+            vec![state]
+        }
+        "mouseup" => {
+            let button = parse_button(rest)?;
+            if !buttons_down.contains(&button) {
+                return Err(format!("'mouseup {}' without 'mousedown'", rest));
+            }
+            let state = unchanged(buttons_down);
+            buttons_down.retain(|&code| code != button);
+            vec![state]
         }
+        _ => vec![parse_line(line)?],
+    };
 
-        let direction = parts[0];
-        let amount: i32 = parts[1]
-            .parse()
-            .map_err(|_| format!("Invalid scroll amount: {}", parts[1]))?;
-
-        let scroll_delta = match direction {
-            "up" => (amount, 0),
-            "down" => (-amount, 0),
-            "left" => (0, -amount),
-            "right" => (0, amount),
-            _ => {
-                return Err(format!(
-                    "Invalid scroll direction '{}', use up/down/left/right",
-                    direction
-                ))
+    // Buttons held down since a `mousedown` were pressed before anything else
+    for state in &mut states {
+        let mut keys = buttons_down.clone();
+        keys.extend(state.keys_pressed.iter().filter(|code| !buttons_down.contains(code)));
+        state.keys_pressed = keys;
+    }
+    states.splice(0..0, release);
+
+    Ok(states)
+}
+
+/// Parse the rest of a `double-click` line into click, pause, click
+fn parse_double_click(rest: &str, line: &str) -> Result<Vec<MacroState>, String> {
+    let mut tokens = rest.split_whitespace();
+    let button = tokens
+        .next()
+        .ok_or_else(|| format!("Invalid 'double-click' syntax: {}", line))?;
+    let button = parse_button(button)?;
+
+    let mut holds = (Duration::ZERO, Duration::ZERO);
+    let mut gap = Duration::from_millis(100);
+    while let Some(option) = tokens.next() {
+        let value = tokens
+            .next()
+            .ok_or_else(|| format!("Invalid 'double-click' syntax: {}", line))?;
+        match option {
+            "for" => {
+                holds = match value.split_once('/') {
+                    Some((first, second)) => (parse_duration(first)?, parse_duration(second)?),
+                    None => (parse_duration(value)?, parse_duration(value)?),
+                };
             }
-        };
+            "gap" => gap = parse_duration(value)?,
+            _ => return Err(format!("Invalid 'double-click' syntax: {}", line)),
+        }
+    }
 
-        return Ok(MacroState {
-            duration_ms: 0,
-            keys_pressed: HashSet::new(),
-            mouse_delta: (0, 0),
-            scroll_delta,
-        });
+    let click = |hold: Duration| MacroState {
+        keys_pressed: vec![button],
+        ..MacroState::new(hold.as_micros() as u64)
+    };
+    let pause = MacroState::new(gap.as_micros() as u64);
+    Ok(vec![click(holds.0), pause, click(holds.1)])
+}
+
+/// Parse a mouse button name like "left" or "BTN_SIDE"
+fn parse_button(name: &str) -> Result<u16, String> {
+    keymap::name_to_mouse_button(name).ok_or_else(|| format!("Unknown mouse button: {}", name))
+}
+
+/// Parse the absolute axis changes of an `abs` clause, like "X=10 Y=20"
+fn parse_abs_changes(s: &str) -> Result<Vec<(u16, i32)>, String> {
+    let mut changes = Vec::new();
+
+    for change in s.split_whitespace() {
+        let (name, value) = change
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid 'abs' value, expected AXIS=VALUE: {}", change))?;
+        let code = keymap::name_to_abs_axis(name).ok_or_else(|| format!("Unknown axis: {}", name))?;
+        let value: i32 = value
+            .parse()
+            .map_err(|_| format!("Invalid axis value: {}", value))?;
+        changes.push((code, value));
     }
 
-    // Parse "tap KEY" or "tap KEY+KEY2"
-    if let Some(rest) = line.strip_prefix("tap ") {
-        let keys = parse_keys(rest)?;
-        return Ok(MacroState {
-            duration_ms: 0,
-            keys_pressed: keys,
-            mouse_delta: (0, 0),
-            scroll_delta: (0, 0),
-        });
+    if changes.is_empty() {
+        return Err("'abs' needs at least one AXIS=VALUE".to_string());
     }
 
-    Err(format!("Unknown command: {}", line))
+    Ok(changes)
 }
 
-/// Parse duration string like "100ms" or "2s"
-fn parse_duration(s: &str) -> Result<u64, String> {
-    if let Some(ms_str) = s.strip_suffix("ms") {
-        ms_str
-            .parse::<u64>()
-            .map_err(|_| format!("Invalid duration: {}", s))
-    } else if let Some(s_str) = s.strip_suffix('s') {
-        s_str
-            .parse::<u64>()
-            .map(|s| s * 1000)
-            .map_err(|_| format!("Invalid duration: {}", s))
+/// Parse duration string like "100ms", "2s", "1.5ms" or "250us"
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (number, unit_us) = if let Some(us) = s.strip_suffix("us").or(s.strip_suffix("µs")) {
+        (us, 1.0)
+    } else if let Some(ms) = s.strip_suffix("ms") {
+        (ms, 1e3)
+    } else if let Some(secs) = s.strip_suffix('s') {
+        (secs, 1e6)
     } else {
-        Err(format!("Duration must end with 'ms' or 's': {}", s))
+        return Err(format!("Duration must end with 'us', 'ms' or 's': {}", s));
+    };
+
+    match number.parse::<f64>() {
+        Ok(value) if value.is_finite() && value >= 0.0 => {
+            Ok(Duration::from_micros((value * unit_us).round() as u64))
+        }
+        _ => Err(format!("Invalid duration: {}", s)),
     }
 }
 
-/// Parse key names like "W" or "W+A+SHIFT"
-fn parse_keys(s: &str) -> Result<HashSet<u16>, String> {
+/// Format microseconds as "12ms", "12.345ms" or "250us", whichever is exact
+fn format_duration(us: u64) -> String {
+    if us % 1000 == 0 {
+        format!("{}ms", us / 1000)
+    } else if us < 1000 {
+        format!("{}us", us)
+    } else {
+        let ms = format!("{}.{:03}", us / 1000, us % 1000);
+        format!("{}ms", ms.trim_end_matches('0'))
+    }
+}
+
+/// Parse key names like "W" or "SHIFT+W+A", in the order they're pressed
+fn parse_keys(s: &str) -> Result<Vec<u16>, String> {
     let key_names: Vec<&str> = s.split('+').collect();
-    let mut keycodes = HashSet::new();
+    let mut keycodes = Vec::new();
 
     for name in key_names {
         let name = name.trim();
         if let Some(code) = keymap::name_to_keycode(name) {
-            keycodes.insert(code);
+            if !keycodes.contains(&code) {
+                keycodes.push(code);
+            }
         } else {
             return Err(format!("Unknown key: {}", name));
         }
@@ -289,26 +890,26 @@ fn parse_keys(s: &str) -> Result<HashSet<u16>, String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use evdev::EventType;
 
     #[test]
     fn test_parse_hold() {
         let state = parse_line("hold W for 100ms").unwrap();
-        assert_eq!(state.duration_ms, 100);
+        assert_eq!(state.duration_us, 100_000);
         assert!(state.keys_pressed.contains(&17)); // W = 17
     }
 
     #[test]
     fn test_parse_hold_multiple() {
         let state = parse_line("hold W+A for 50ms").unwrap();
-        assert_eq!(state.duration_ms, 50);
-        assert!(state.keys_pressed.contains(&17)); // W
-        assert!(state.keys_pressed.contains(&30)); // A
+        assert_eq!(state.duration_us, 50_000);
+        assert_eq!(state.keys_pressed, vec![17, 30]); // W, A in that order
     }
 
     #[test]
     fn test_parse_wait() {
         let state = parse_line("wait 200ms").unwrap();
-        assert_eq!(state.duration_ms, 200);
+        assert_eq!(state.duration_us, 200_000);
         assert!(state.keys_pressed.is_empty());
     }
 
@@ -335,23 +936,477 @@ mod tests {
 
     #[test]
     fn test_parse_duration() {
-        assert_eq!(parse_duration("100ms").unwrap(), 100);
-        assert_eq!(parse_duration("2s").unwrap(), 2000);
+        assert_eq!(parse_duration("100ms").unwrap(), Duration::from_millis(100));
+        assert_eq!(parse_duration("2s").unwrap(), Duration::from_secs(2));
+        assert_eq!(parse_duration("1.5ms").unwrap(), Duration::from_micros(1_500));
+        assert_eq!(parse_duration("250us").unwrap(), Duration::from_micros(250));
         assert!(parse_duration("100").is_err());
+        assert!(parse_duration("-1ms").is_err());
+        assert!(parse_duration("infms").is_err());
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(12_000), "12ms");
+        assert_eq!(format_duration(12_340), "12.34ms");
+        assert_eq!(format_duration(1_005), "1.005ms");
+        assert_eq!(format_duration(250), "250us");
+        for us in [12_000, 12_340, 1_005, 250] {
+            let parsed = parse_duration(&format_duration(us)).unwrap();
+            assert_eq!(parsed.as_micros() as u64, us);
+        }
     }
 
     #[test]
     fn test_format_scroll_with_duration() {
         // State with scroll and duration should output scroll + wait
         let state = MacroState {
-            duration_ms: 500,
-            keys_pressed: HashSet::new(),
+            duration_us: 500_000,
+            keys_pressed: Vec::new(),
             mouse_delta: (0, 0),
             scroll_delta: (-1, 0), // scroll down
+            abs_changes: Vec::new(),
         };
 
         let formatted = format_state(&state);
         assert!(formatted.contains("scroll down 1"));
         assert!(formatted.contains("wait 500ms"));
     }
+
+    #[test]
+    fn test_parse_abs() {
+        let state = parse_line("abs X=100 Y=200 PRESSURE=40").unwrap();
+        assert_eq!(state.abs_changes, vec![(0, 100), (1, 200), (0x18, 40)]);
+        assert!(state.keys_pressed.is_empty());
+
+        let state = parse_line("hold BTN_TOUCH for 8ms abs MT_SLOT=1 MT_TRACKING_ID=-1").unwrap();
+        assert_eq!(state.duration_us, 8_000);
+        assert!(state.keys_pressed.contains(&330));
+        assert_eq!(state.abs_changes, vec![(0x2f, 1), (0x39, -1)]);
+
+        assert!(parse_line("abs X").is_err());
+        assert!(parse_line("abs NOPE=1").is_err());
+    }
+
+    #[test]
+    fn test_format_abs_roundtrip() {
+        let mut state = MacroState::new(8_000);
+        state.keys_pressed.push(330); // BTN_TOUCH
+        state.abs_changes = vec![(0, 512), (1, 300)];
+
+        let formatted = format_state(&state);
+        assert_eq!(formatted, "hold BTN_TOUCH for 8ms abs X=512 Y=300");
+        assert_eq!(parse_line(&formatted).unwrap(), state);
+    }
+
+    #[test]
+    fn test_unnamed_button() {
+        // BTN_TRIGGER_HAPPY1, a D-pad direction on many controllers
+        let mut state = MacroState::new(100_000);
+        state.keys_pressed.push(0x2c0);
+        let formatted = format_state(&state);
+        assert_eq!(formatted, "hold KEY_704 for 100ms");
+        assert_eq!(parse_line(&formatted).unwrap(), state);
+
+        let device = AbsDevice {
+            buttons: vec![304, 0x2c0],
+            ..AbsDevice::default()
+        };
+        let mut written = Vec::new();
+        write_abs_device(&mut written, &device).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert!(written.contains("buttons BTN_SOUTH KEY_704\n"));
+
+        let mut loaded = AbsDevice::default();
+        assert!(parse_device_line("buttons BTN_SOUTH KEY_704", &mut loaded).unwrap().is_ok());
+        assert_eq!(loaded.buttons, vec![304, 0x2c0]);
+    }
+
+    #[test]
+    fn test_unnamed_axis() {
+        // An extra joystick axis hid-input puts past ABS_MISC
+        let mut state = MacroState::new(0);
+        state.abs_changes = vec![(0x29, 200)];
+        let formatted = format_state(&state);
+        assert_eq!(formatted, "abs ABS_41=200");
+        assert_eq!(parse_line(&formatted).unwrap(), state);
+
+        let device = AbsDevice {
+            axes: vec![(0x29, AbsInfo::new(0, 0, 255, 0, 15, 0))],
+            ..AbsDevice::default()
+        };
+        let mut written = Vec::new();
+        write_abs_device(&mut written, &device).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert!(written.contains("axis ABS_41 0 255 0 15 0\n"), "{}", written);
+
+        let mut loaded = AbsDevice::default();
+        assert!(parse_device_line("axis ABS_41 0 255 0 15 0", &mut loaded).unwrap().is_ok());
+        assert_eq!(loaded.axes, device.axes);
+    }
+
+    #[test]
+    fn test_parse_combined_clauses() {
+        let state = parse_line("hold W for 120ms move 15 3").unwrap();
+        assert_eq!(state.keys_pressed, vec![17]);
+        assert_eq!(state.duration_us, 120_000);
+        assert_eq!(state.mouse_delta, (15, 3));
+
+        let state = parse_line("tap W + A move 1 2 scroll up 1 scroll left 2").unwrap();
+        assert_eq!(state.keys_pressed, vec![17, 30]);
+        assert_eq!(state.duration_us, 0);
+        assert_eq!(state.mouse_delta, (1, 2));
+        assert_eq!(state.scroll_delta, (1, -2));
+
+        assert!(parse_line("hold W for 10ms hold A for 10ms").is_err());
+        assert!(parse_line("hold W for 10ms wait 10ms").is_err());
+        assert!(parse_line("scroll up 1 scroll down 1").is_err());
+        assert!(parse_line("move 1").is_err());
+        assert!(parse_line("hold W move 1 2").is_err());
+    }
+
+    #[test]
+    fn test_parse_mouse_commands() {
+        let state = parse_line("click left for 87ms").unwrap();
+        assert_eq!(state.keys_pressed, vec![272]);
+        assert_eq!(state.duration_us, 87_000);
+
+        let state = parse_line("drag 200 -40 with BTN_SIDE").unwrap();
+        assert_eq!(state.keys_pressed, vec![275]);
+        assert_eq!(state.mouse_delta, (200, -40));
+        assert_eq!(state.duration_us, 0);
+
+        let mut down = Vec::new();
+        let states = parse_statement("double-click right for 50ms gap 120ms", &mut down, &[]).unwrap();
+        let parts: Vec<(Vec<u16>, u64)> = states
+            .iter()
+            .map(|state| (state.keys_pressed.clone(), state.duration_us))
+            .collect();
+        assert_eq!(
+            parts,
+            vec![(vec![273], 50_000), (vec![], 120_000), (vec![273], 50_000)]
+        );
+        let states = parse_statement("double-click left for 62ms/58ms", &mut down, &[]).unwrap();
+        assert_eq!(states[0].duration_us, 62_000);
+        assert_eq!(states[1].duration_us, 100_000);
+        assert_eq!(states[2].duration_us, 58_000);
+        assert!(parse_statement("double-click left for 62ms/", &mut down, &[]).is_err());
+
+        let states = parse_statement("mousedown left", &mut down, &[29]).unwrap();
+        assert_eq!(states[0].keys_pressed, vec![272, 29]);
+        let states = parse_statement("hold CTRL for 10ms", &mut down, &[272, 29]).unwrap();
+        assert_eq!(states[0].keys_pressed, vec![272, 29]);
+        let states = parse_statement("mouseup left", &mut down, &[272, 29]).unwrap();
+        assert_eq!(states[0].keys_pressed, vec![29]);
+        assert!(down.is_empty());
+
+        assert!(parse_statement("mouseup left", &mut down, &[]).is_err());
+        assert!(parse_line("click W").is_err());
+        assert!(parse_line("drag 1 2 left").is_err());
+        assert!(parse_line("click left move 1 2").is_ok());
+        assert!(parse_line("drag 1 2 with left move 1 2").is_err());
+    }
+
+    #[test]
+    fn test_format_mouse_commands() {
+        let click = MacroState {
+            keys_pressed: vec![272],
+            ..MacroState::new(87_000)
+        };
+        assert_eq!(format_state(&click), "click left for 87ms");
+
+        let drag = MacroState {
+            mouse_delta: (200, 40),
+            ..click.clone()
+        };
+        assert_eq!(format_state(&drag), "drag 200 40 with left for 87ms");
+
+        let pause = MacroState::new(120_000);
+        let lines = format_states(&[click.clone(), pause.clone(), click.clone()]);
+        assert_eq!(lines, vec!["double-click left for 87ms gap 120ms"]);
+
+        // Too slow for a double-click
+        let long_pause = MacroState::new(2_000_000);
+        let lines = format_states(&[click.clone(), long_pause, click.clone()]);
+        assert_eq!(lines, vec!["click left for 87ms", "wait 2000ms", "click left for 87ms"]);
+
+        let lines = format_states(&[click.clone(), drag, pause]);
+        assert_eq!(
+            lines,
+            vec![
+                "mousedown left",
+                "wait 87ms",
+                "move 200 40\nwait 87ms",
+                "mouseup left",
+                "wait 120ms"
+            ]
+        );
+    }
+
+    fn event(timestamp_ms: u64, event_type: EventType, code: u16, value: i32) -> RecordedEvent {
+        RecordedEvent {
+            timestamp_us: timestamp_ms * 1000,
+            event: InputEvent::new(event_type.0, code, value),
+        }
+    }
+
+    /// Save a macro and load it again, in memory
+    fn round_trip(events: &[RecordedEvent]) -> Vec<RecordedEvent> {
+        let recording = Macro {
+            events: events.to_vec(),
+            ..Macro::default()
+        };
+        let mut written = Vec::new();
+        write_macro(&mut written, &recording).unwrap();
+        read_macro(written.as_slice()).unwrap().events
+    }
+
+    /// The input events as (time, type, code, value), without the
+    /// SYN_REPORTs that only group them
+    fn stream(events: &[RecordedEvent]) -> Vec<(u64, u16, u16, i32)> {
+        events
+            .iter()
+            .filter(|e| e.event.event_type() != EventType::SYNCHRONIZATION)
+            .map(|e| {
+                (e.timestamp_us, e.event.event_type().0, e.event.code(), e.event.value())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip_hold_with_mouse() {
+        let events = vec![
+            event(0, EventType::KEY, 17, 1), // W
+            event(120, EventType::RELATIVE, 0, 15),
+            event(120, EventType::RELATIVE, 1, 3),
+            event(240, EventType::RELATIVE, 0, 10),
+            event(240, EventType::RELATIVE, 1, -6),
+            event(400, EventType::KEY, 17, 0),
+            event(500, EventType::KEY, 30, 1), // A
+            event(600, EventType::KEY, 30, 0),
+        ];
+        assert_eq!(stream(&round_trip(&events)), stream(&events));
+    }
+
+    #[test]
+    fn test_round_trip_chord() {
+        let events = vec![
+            event(0, EventType::KEY, 29, 1), // CTRL
+            event(30, EventType::KEY, 46, 1), // C
+            event(80, EventType::KEY, 46, 0),
+            event(100, EventType::KEY, 29, 0),
+        ];
+        assert_eq!(stream(&round_trip(&events)), stream(&events));
+    }
+
+    #[test]
+    fn test_round_trip_scroll_and_moves_without_keys() {
+        let events = vec![
+            event(0, EventType::KEY, 42, 1), // SHIFT
+            event(50, EventType::RELATIVE, 8, 2), // REL_WHEEL
+            event(100, EventType::KEY, 42, 0),
+            event(300, EventType::RELATIVE, 0, 20),
+            event(400, EventType::RELATIVE, 6, -1), // REL_HWHEEL
+            event(500, EventType::KEY, 18, 1), // E
+            event(550, EventType::KEY, 18, 0),
+        ];
+        assert_eq!(stream(&round_trip(&events)), stream(&events));
+    }
+
+    /// The events grouped into frames at each SYN_REPORT, as
+    /// (time, type, code, value)
+    fn frames(events: &[RecordedEvent]) -> Vec<Vec<(u64, u16, u16, i32)>> {
+        events
+            .split(|e| e.event.event_type() == EventType::SYNCHRONIZATION && e.event.code() == 0)
+            .filter(|frame| !frame.is_empty())
+            .map(stream)
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip_touch() {
+        let syn = |timestamp_ms| event(timestamp_ms, EventType::SYNCHRONIZATION, 0, 0);
+        let events = vec![
+            event(0, EventType::ABSOLUTE, 0x39, 7), // MT_TRACKING_ID
+            event(0, EventType::ABSOLUTE, 0, 512),
+            event(0, EventType::ABSOLUTE, 1, 300),
+            event(0, EventType::KEY, 330, 1), // BTN_TOUCH
+            syn(0),
+            event(10, EventType::ABSOLUTE, 0, 520),
+            syn(10),
+            event(20, EventType::ABSOLUTE, 0x39, -1),
+            event(20, EventType::KEY, 330, 0),
+            syn(20),
+        ];
+        assert_eq!(frames(&round_trip(&events)), frames(&events));
+    }
+
+    #[test]
+    fn test_round_trip_mouse_buttons() {
+        let events = vec![
+            // Double-click, the second click shorter as usual
+            event(0, EventType::KEY, 272, 1),
+            event(60, EventType::KEY, 272, 0),
+            event(150, EventType::KEY, 272, 1),
+            event(208, EventType::KEY, 272, 0),
+            // Drag along a path, right-clicking on the way
+            event(1000, EventType::KEY, 272, 1),
+            event(1010, EventType::RELATIVE, 0, 30),
+            event(1020, EventType::RELATIVE, 1, 30),
+            event(1030, EventType::KEY, 273, 1),
+            event(1040, EventType::KEY, 273, 0),
+            event(1050, EventType::KEY, 272, 0),
+            // Back button
+            event(2000, EventType::KEY, 278, 1),
+            event(2080, EventType::KEY, 278, 0),
+        ];
+        assert_eq!(stream(&round_trip(&events)), stream(&events));
+
+        let recording = Macro {
+            events,
+            ..Macro::default()
+        };
+        let mut written = Vec::new();
+        write_macro(&mut written, &recording).unwrap();
+        let text = String::from_utf8(written).unwrap();
+        assert!(text.contains("double-click left for 60ms/58ms gap 90ms\n"), "{}", text);
+    }
+
+    #[test]
+    fn test_round_trip_modifier_over_mousedown() {
+        let events = vec![
+            event(0, EventType::KEY, 42, 1), // SHIFT
+            event(100, EventType::KEY, 272, 1), // BTN_LEFT
+            event(120, EventType::RELATIVE, 0, 30),
+            event(140, EventType::RELATIVE, 1, 30),
+            event(150, EventType::KEY, 272, 0),
+            event(200, EventType::KEY, 42, 0),
+        ];
+        assert_eq!(stream(&round_trip(&events)), stream(&events));
+    }
+
+    #[test]
+    fn test_mousedown_after_click() {
+        for click in ["click left", "click left for 80ms", "drag 10 0 with left"] {
+            let text = format!("{}\nmousedown left\nwait 50ms\nmouseup left\n", click);
+            let loaded = read_macro(text.as_bytes()).unwrap();
+            let presses: Vec<i32> = stream(&loaded.events)
+                .iter()
+                .filter(|&&(_, _, code, _)| code == 272)
+                .map(|&(_, _, _, value)| value)
+                .collect();
+            assert_eq!(presses, vec![1, 0, 1, 0], "{}", click);
+        }
+    }
+
+    #[test]
+    fn test_raw_round_trip_is_exact() {
+        let events = vec![
+            RecordedEvent {
+                timestamp_us: 0,
+                event: InputEvent::new(EventType::MISC.0, 4, 0x70004), // MSC_SCAN
+            },
+            RecordedEvent {
+                timestamp_us: 0,
+                event: InputEvent::new(EventType::KEY.0, 30, 1),
+            },
+            RecordedEvent {
+                timestamp_us: 0,
+                event: InputEvent::new(EventType::SYNCHRONIZATION.0, 0, 0),
+            },
+            RecordedEvent {
+                timestamp_us: 250_123,
+                event: InputEvent::new(EventType::KEY.0, 30, 2), // repeat
+            },
+            RecordedEvent {
+                timestamp_us: 250_123,
+                event: InputEvent::new(EventType::SYNCHRONIZATION.0, 0, 0),
+            },
+            RecordedEvent {
+                timestamp_us: 250_987,
+                event: InputEvent::new(EventType::RELATIVE.0, 0, 1), // 1px, kept
+            },
+            RecordedEvent {
+                timestamp_us: 250_987,
+                event: InputEvent::new(EventType::SYNCHRONIZATION.0, 0, 0),
+            },
+        ];
+        let recording = Macro {
+            events: events.clone(),
+            format: Format::Raw,
+            ..Macro::default()
+        };
+
+        let mut written = Vec::new();
+        write_macro(&mut written, &recording).unwrap();
+        let loaded = read_macro(written.as_slice()).unwrap();
+
+        assert_eq!(loaded.format, Format::Raw);
+        let exact = |events: &[RecordedEvent]| -> Vec<(u64, u16, u16, i32)> {
+            events
+                .iter()
+                .map(|e| (e.timestamp_us, e.event.event_type().0, e.event.code(), e.event.value()))
+                .collect()
+        };
+        assert_eq!(exact(&loaded.events), exact(&events));
+    }
+
+    #[test]
+    fn test_raw_format_errors() {
+        assert!(read_macro("format raw 2\n".as_bytes()).is_err());
+        assert!(read_macro("format raw 1\n0 1 30\n".as_bytes()).is_err());
+        assert!(read_macro("format raw 1\nhold W for 10ms\n".as_bytes()).is_err());
+        assert!(read_macro("hold W for 10ms\nformat raw 1\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_round_trip_through_file() {
+        let path = std::env::temp_dir().join(format!("evkey-test-{}.macro", std::process::id()));
+        let events = vec![
+            event(0, EventType::KEY, 17, 1),
+            event(120, EventType::RELATIVE, 0, 15),
+            event(120, EventType::RELATIVE, 1, 3),
+            event(400, EventType::KEY, 17, 0),
+        ];
+        let recording = Macro {
+            events: events.clone(),
+            ..Macro::default()
+        };
+
+        save(&path, &recording).unwrap();
+        let loaded = load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(stream(&loaded.unwrap().events), stream(&events));
+    }
+
+    #[test]
+    fn test_parse_device_lines() {
+        let mut device = AbsDevice::default();
+
+        let line = "device gamepad 0003:045e:02ea:0408 Xbox Wireless Controller";
+        assert!(parse_device_line(line, &mut device).unwrap().is_ok());
+        assert!(parse_device_line("device toaster 0:0:0:0", &mut device).unwrap().is_err());
+        assert!(parse_device_line("device gamepad 045e:02ea", &mut device).unwrap().is_err());
+        assert!(parse_device_line("axis X 0 32767 0 0 100", &mut device).unwrap().is_ok());
+        assert!(parse_device_line("buttons BTN_TOUCH BTN_TOOL_PEN", &mut device).unwrap().is_ok());
+        assert!(parse_device_line("properties DIRECT", &mut device).unwrap().is_ok());
+        assert!(parse_device_line("hold W for 10ms", &mut device).is_none());
+        assert!(parse_device_line("axis X 0 100", &mut device).unwrap().is_err());
+
+        assert_eq!(device.kind, Some(DeviceKind::Gamepad));
+        assert_eq!(device.id, Some((0x0003, 0x045e, 0x02ea, 0x0408)));
+        assert_eq!(device.name.as_deref(), Some("Xbox Wireless Controller"));
+        assert_eq!(device.axes, vec![(0, AbsInfo::new(0, 0, 32767, 0, 0, 100))]);
+        assert_eq!(device.buttons, vec![330, 320]);
+        assert_eq!(device.properties, vec![1]);
+
+        let mut written = Vec::new();
+        write_abs_device(&mut written, &device).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert!(written.contains("device gamepad 0003:045e:02ea:0408 Xbox Wireless Controller\n"));
+        assert!(written.contains("axis X 0 32767 0 0 100\n"));
+        assert!(written.contains("buttons BTN_TOUCH BTN_TOOL_PEN\n"));
+        assert!(written.contains("properties DIRECT\n"));
+    }
 }